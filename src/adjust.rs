@@ -0,0 +1,471 @@
+//! Adjustments change each pixel on its own: tones, colors and the like
+
+use egui::*;
+use micro_ndarray::Array;
+
+use crate::{
+    color::{hsl_to_rgb, rgb_to_hsl},
+    popup::{Popup, PreviewDialog, Previewed},
+    App,
+};
+
+#[derive(Clone, PartialEq)]
+pub enum Adjustment {
+    Levels {
+        input: [f32; 2],
+        gamma: f32,
+        output: [f32; 2],
+    },
+    /// control points per channel: master, red, green, blue
+    Curves {
+        points: [Vec<[f32; 2]>; 4],
+        channel: usize,
+    },
+    HueSaturation {
+        hue: f32,
+        saturation: f32,
+        lightness: f32,
+    },
+    BrightnessContrast {
+        brightness: f32,
+        contrast: f32,
+    },
+    Invert,
+    Grayscale {
+        weights: [f32; 3],
+    },
+    Sepia {
+        strength: f32,
+    },
+    Threshold {
+        level: f32,
+    },
+}
+
+use Adjustment::*;
+
+impl Adjustment {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Levels { .. } => "Levels",
+            Curves { .. } => "Curves",
+            HueSaturation { .. } => "Hue/Saturation",
+            BrightnessContrast { .. } => "Brightness/Contrast",
+            Invert => "Invert",
+            Grayscale { .. } => "Grayscale",
+            Sepia { .. } => "Sepia",
+            Threshold { .. } => "Threshold",
+        }
+    }
+
+    /// The parameter widgets, returns true if any were changed
+    fn params(&mut self, ui: &mut Ui, histogram: &[u32], dragging: &mut Option<usize>) -> bool {
+        match self {
+            Levels {
+                input,
+                gamma,
+                output,
+            } => {
+                render_histogram(ui, histogram);
+                ui.add(Slider::new(&mut input[0], 0.0..=254.0).text("input black"))
+                    .changed()
+                    | ui.add(Slider::new(&mut input[1], 1.0..=255.0).text("input white"))
+                        .changed()
+                    | ui.add(
+                        Slider::new(gamma, 0.1..=10.0)
+                            .logarithmic(true)
+                            .text("gamma"),
+                    )
+                    .changed()
+                    | ui.add(Slider::new(&mut output[0], 0.0..=255.0).text("output black"))
+                        .changed()
+                    | ui.add(Slider::new(&mut output[1], 0.0..=255.0).text("output white"))
+                        .changed()
+            }
+            Curves { points, channel } => {
+                ui.horizontal(|ui| {
+                    ui.selectable_value(channel, 0, "RGB");
+                    ui.selectable_value(channel, 1, "Red");
+                    ui.selectable_value(channel, 2, "Green");
+                    ui.selectable_value(channel, 3, "Blue");
+                });
+                curve_editor(ui, &mut points[*channel], histogram, dragging)
+            }
+            HueSaturation {
+                hue,
+                saturation,
+                lightness,
+            } => {
+                ui.add(Slider::new(hue, -180.0..=180.0).text("hue"))
+                    .changed()
+                    | ui.add(Slider::new(saturation, -100.0..=100.0).text("saturation"))
+                        .changed()
+                    | ui.add(Slider::new(lightness, -100.0..=100.0).text("lightness"))
+                        .changed()
+            }
+            BrightnessContrast {
+                brightness,
+                contrast,
+            } => {
+                ui.add(Slider::new(brightness, -100.0..=100.0).text("brightness"))
+                    .changed()
+                    | ui.add(Slider::new(contrast, -100.0..=100.0).text("contrast"))
+                        .changed()
+            }
+            Invert => false,
+            Grayscale { weights } => {
+                ui.add(Slider::new(&mut weights[0], 0.0..=1.0).text("red"))
+                    .changed()
+                    | ui.add(Slider::new(&mut weights[1], 0.0..=1.0).text("green"))
+                        .changed()
+                    | ui.add(Slider::new(&mut weights[2], 0.0..=1.0).text("blue"))
+                        .changed()
+            }
+            Sepia { strength } => ui
+                .add(Slider::new(strength, 0.0..=1.0).text("strength"))
+                .changed(),
+            Threshold { level } => {
+                render_histogram(ui, histogram);
+                ui.add(Slider::new(level, 0.0..=255.0).text("level"))
+                    .changed()
+            }
+        }
+    }
+
+    /// Turns the adjustment into a function on unmultiplied rgb values (0..1)
+    fn pixel_fn(&self) -> Box<dyn Fn([f32; 3]) -> [f32; 3]> {
+        match self.clone() {
+            Levels {
+                input,
+                gamma,
+                output,
+            } => Box::new(move |px| {
+                px.map(|c| {
+                    let c =
+                        ((c * 255.0 - input[0]) / (input[1] - input[0]).max(1.0)).clamp(0.0, 1.0);
+                    (output[0] + c.powf(1.0 / gamma) * (output[1] - output[0])) / 255.0
+                })
+            }),
+            Curves { points, .. } => {
+                let luts = points.each_ref().map(|points| curve_lut(points));
+                Box::new(move |px| {
+                    let mut out = [0.0; 3];
+                    for c in 0..3 {
+                        // master curve first, then the channel's own
+                        let v = luts[0][(px[c] * 255.0).round() as usize];
+                        out[c] = luts[c + 1][v.round() as usize] / 255.0;
+                    }
+                    out
+                })
+            }
+            HueSaturation {
+                hue,
+                saturation,
+                lightness,
+            } => Box::new(move |px| {
+                let [h, s, l] = rgb_to_hsl(px);
+                let s = (s * (1.0 + saturation / 100.0)).clamp(0.0, 1.0);
+                let l = if lightness < 0.0 {
+                    l * (1.0 + lightness / 100.0)
+                } else {
+                    l + (1.0 - l) * lightness / 100.0
+                };
+                hsl_to_rgb([h + hue, s, l])
+            }),
+            BrightnessContrast {
+                brightness,
+                contrast,
+            } => {
+                // the usual contrast curve, 100 makes it (almost) a threshold
+                let c = contrast.min(99.0) / 100.0;
+                let factor = (1.0 + c) / (1.0 - c);
+                Box::new(move |px| px.map(|v| (v + brightness / 100.0 - 0.5) * factor + 0.5))
+            }
+            Invert => Box::new(|px| px.map(|v| 1.0 - v)),
+            Grayscale { weights } => {
+                let total = weights.iter().sum::<f32>().max(0.001);
+                Box::new(move |[r, g, b]| {
+                    let l = (r * weights[0] + g * weights[1] + b * weights[2]) / total;
+                    [l, l, l]
+                })
+            }
+            Sepia { strength } => Box::new(move |[r, g, b]| {
+                let sepia = [
+                    0.393 * r + 0.769 * g + 0.189 * b,
+                    0.349 * r + 0.686 * g + 0.168 * b,
+                    0.272 * r + 0.534 * g + 0.131 * b,
+                ];
+                [
+                    r + (sepia[0] - r) * strength,
+                    g + (sepia[1] - g) * strength,
+                    b + (sepia[2] - b) * strength,
+                ]
+            }),
+            Threshold { level } => Box::new(move |[r, g, b]| {
+                let l = luminance([r, g, b]) * 255.0;
+                if l >= level {
+                    [1.0; 3]
+                } else {
+                    [0.0; 3]
+                }
+            }),
+        }
+    }
+
+    pub fn apply(&self, image: &Array<Color32, 2>) -> Array<Color32, 2> {
+        let f = self.pixel_fn();
+        let px = image
+            .as_flattened()
+            .iter()
+            .map(|px| {
+                let [r, g, b, a] = px.to_srgba_unmultiplied();
+                let [r, g, b] = f([r, g, b].map(|c| c as f32 / 255.0))
+                    .map(|c| (c * 255.0).round().clamp(0.0, 255.0) as u8);
+                Color32::from_rgba_unmultiplied(r, g, b, a)
+            })
+            .collect();
+        Array::from_flat(px, image.size()).unwrap()
+    }
+}
+
+pub fn luminance([r, g, b]: [f32; 3]) -> f32 {
+    0.299 * r + 0.587 * g + 0.114 * b
+}
+
+/// Evaluates a curve through sorted control points for every input value
+fn curve_lut(points: &[[f32; 2]]) -> [f32; 256] {
+    let mut lut = [0.0; 256];
+    // slope at each control point, from its neighbors
+    let slope = |i: usize| {
+        let a = points[i.saturating_sub(1)];
+        let b = points[(i + 1).min(points.len() - 1)];
+        (b[1] - a[1]) / (b[0] - a[0]).max(1.0)
+    };
+    for (x, out) in lut.iter_mut().enumerate() {
+        let x = x as f32;
+        let i = points
+            .windows(2)
+            .position(|w| x <= w[1][0])
+            .unwrap_or(points.len().saturating_sub(2));
+        let (a, b) = (points[i], points[(i + 1).min(points.len() - 1)]);
+        let y = if x <= a[0] {
+            a[1]
+        } else if x >= b[0] {
+            b[1]
+        } else {
+            // cubic hermite interpolation between a and b
+            let dx = b[0] - a[0];
+            let t = (x - a[0]) / dx;
+            let (t2, t3) = (t * t, t * t * t);
+            (2.0 * t3 - 3.0 * t2 + 1.0) * a[1]
+                + (t3 - 2.0 * t2 + t) * dx * slope(i)
+                + (-2.0 * t3 + 3.0 * t2) * b[1]
+                + (t3 - t2) * dx * slope(i + 1)
+        };
+        *out = y.clamp(0.0, 255.0);
+    }
+    lut
+}
+
+fn render_histogram(ui: &mut Ui, histogram: &[u32]) -> Rect {
+    let (rect, _) = ui.allocate_exact_size(vec2(256.0, 80.0), Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, Color32::from_gray(30));
+    let max = *histogram.iter().max().unwrap_or(&1) as f32;
+    for (x, &count) in histogram.iter().enumerate() {
+        let x = rect.min.x + x as f32 + 0.5;
+        let height = count as f32 / max.max(1.0) * rect.height();
+        painter.vline(
+            x,
+            rect.max.y - height..=rect.max.y,
+            Stroke::new(1.0, Color32::GRAY),
+        );
+    }
+    rect
+}
+
+/// Drag points to move them, drag anywhere else to add one, right click removes one
+fn curve_editor(
+    ui: &mut Ui,
+    points: &mut Vec<[f32; 2]>,
+    histogram: &[u32],
+    dragging: &mut Option<usize>,
+) -> bool {
+    let (rect, response) = ui.allocate_exact_size(vec2(256.0, 256.0), Sense::click_and_drag());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, Color32::from_gray(30));
+    let to_screen = |[x, y]: [f32; 2]| pos2(rect.min.x + x, rect.max.y - y);
+    let from_screen = |pos: Pos2| {
+        [
+            (pos.x - rect.min.x).clamp(0.0, 255.0),
+            (rect.max.y - pos.y).clamp(0.0, 255.0),
+        ]
+    };
+    let closest = |points: &[[f32; 2]], pos: Pos2| {
+        points
+            .iter()
+            .position(|&p| to_screen(p).distance(pos) < 8.0)
+    };
+
+    // faint histogram in the background
+    let max = *histogram.iter().max().unwrap_or(&1) as f32;
+    for (x, &count) in histogram.iter().enumerate() {
+        let height = count as f32 / max.max(1.0) * rect.height();
+        painter.vline(
+            rect.min.x + x as f32 + 0.5,
+            rect.max.y - height..=rect.max.y,
+            Stroke::new(1.0, Color32::from_gray(60)),
+        );
+    }
+
+    let mut changed = false;
+    if let Some(pos) = response.interact_pointer_pos() {
+        if response.drag_started() {
+            *dragging = closest(points, pos).or_else(|| {
+                // add a new point where it belongs in the sorted list, unless it's too close to a neighbor
+                let new = from_screen(pos);
+                let i = points
+                    .iter()
+                    .position(|p| p[0] > new[0])
+                    .unwrap_or(points.len());
+                let too_close = |p: &[f32; 2]| (p[0] - new[0]).abs() < 1.0;
+                if points.get(i).is_some_and(too_close)
+                    || i.checked_sub(1).is_some_and(|j| too_close(&points[j]))
+                {
+                    return None;
+                }
+                points.insert(i, new);
+                changed = true;
+                Some(i)
+            });
+        }
+        if let Some(i) = *dragging {
+            let mut new = from_screen(pos);
+            // the end points stay at the ends, the others stay between their neighbors
+            if i == 0 {
+                new[0] = 0.0;
+            } else if i == points.len() - 1 {
+                new[0] = 255.0;
+            } else {
+                let (min, max) = (points[i - 1][0] + 1.0, points[i + 1][0] - 1.0);
+                new[0] = if min <= max {
+                    new[0].clamp(min, max)
+                } else {
+                    // no room left between the neighbors
+                    (points[i - 1][0] + points[i + 1][0]) / 2.0
+                };
+            }
+            changed |= points[i] != new;
+            points[i] = new;
+        }
+        if response.secondary_clicked() {
+            if let Some(i) = closest(points, pos) {
+                if i != 0 && i != points.len() - 1 {
+                    points.remove(i);
+                    changed = true;
+                }
+            }
+        }
+    }
+    if response.drag_released() {
+        *dragging = None;
+    }
+
+    let lut = curve_lut(points);
+    let line: Vec<Pos2> = lut
+        .iter()
+        .enumerate()
+        .map(|(x, &y)| to_screen([x as f32, y]))
+        .collect();
+    painter.add(Shape::line(line, Stroke::new(1.5, Color32::WHITE)));
+    for &p in points.iter() {
+        painter.circle_stroke(to_screen(p), 4.0, Stroke::new(1.0, Color32::YELLOW));
+    }
+    changed
+}
+
+pub type AdjustDialog = PreviewDialog<AdjustParams>;
+
+/// An adjustment with what its dialog shows besides the parameters
+pub struct AdjustParams {
+    adjustment: Adjustment,
+    /// luminance histogram of the (selected part of the) original image
+    histogram: Vec<u32>,
+    /// the curve point being dragged
+    dragging: Option<usize>,
+}
+
+impl AdjustParams {
+    pub fn new(app: &App, adjustment: Adjustment) -> Self {
+        let mut histogram = vec![0; 256];
+        for (pos, px) in app.image.iter() {
+            if app.is_selected(pos[0], pos[1]) {
+                let [r, g, b, _] = px.to_srgba_unmultiplied();
+                histogram[luminance([r, g, b].map(|c| c as f32)).round() as usize] += 1;
+            }
+        }
+        Self {
+            adjustment,
+            histogram,
+            dragging: None,
+        }
+    }
+}
+
+impl Previewed for AdjustParams {
+    fn title(&self) -> &'static str {
+        self.adjustment.name()
+    }
+
+    fn params(&mut self, ui: &mut Ui) -> bool {
+        self.adjustment
+            .params(ui, &self.histogram, &mut self.dragging)
+    }
+
+    fn result(&mut self, original: &Array<Color32, 2>) -> Array<Color32, 2> {
+        self.adjustment.apply(original)
+    }
+}
+
+impl App {
+    pub fn adjust_menu(&mut self, ui: &mut Ui) {
+        let identity = vec![[0.0, 0.0], [255.0, 255.0]];
+        for adjustment in [
+            Levels {
+                input: [0.0, 255.0],
+                gamma: 1.0,
+                output: [0.0, 255.0],
+            },
+            Curves {
+                points: [
+                    identity.clone(),
+                    identity.clone(),
+                    identity.clone(),
+                    identity,
+                ],
+                channel: 0,
+            },
+            HueSaturation {
+                hue: 0.0,
+                saturation: 0.0,
+                lightness: 0.0,
+            },
+            BrightnessContrast {
+                brightness: 0.0,
+                contrast: 0.0,
+            },
+            Invert,
+            Grayscale {
+                weights: [0.299, 0.587, 0.114],
+            },
+            Sepia { strength: 1.0 },
+            Threshold { level: 128.0 },
+        ] {
+            if ui.button(format!("{}...", adjustment.name())).clicked() {
+                let params = AdjustParams::new(self, adjustment);
+                self.popup = Some(Popup::Adjust(AdjustDialog::new(self, params)));
+                ui.close_menu();
+            }
+        }
+    }
+}
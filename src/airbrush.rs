@@ -0,0 +1,80 @@
+//! The airbrush, which sprays random dots around the pointer for as long as it is held
+
+use std::f32::consts::TAU;
+
+use egui::*;
+
+use crate::{draw::Location, effects::rand_f32, App};
+
+/// Dots are sprayed in steps of this many seconds, so the frame rate doesn't change the result
+const STEP: f32 = 1.0 / 120.0;
+
+pub struct Airbrush {
+    /// dots per second
+    pub density: f32,
+    pub radius: f32,
+    /// 0 sprays evenly, 1 sprays mostly around the center
+    pub falloff: f32,
+    /// its own random state, so other effects don't change where the dots land
+    rand: u64,
+    /// the time held that hasn't been sprayed yet
+    time: f32,
+    /// the fraction of a dot left over from the last step
+    carry: f32,
+}
+
+impl Airbrush {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            density: 500.0,
+            radius: 20.0,
+            falloff: 0.5,
+            rand: seed,
+            time: 0.0,
+            carry: 0.0,
+        }
+    }
+
+    /// Starts the random results over from the seed
+    pub fn reseed(&mut self, seed: u64) {
+        self.rand = seed;
+        self.time = 0.0;
+        self.carry = 0.0;
+    }
+
+    pub fn menu(app: &mut App, ui: &mut Ui) {
+        let airbrush = &mut app.airbrush;
+        ui.add(
+            Slider::new(&mut airbrush.density, 10.0..=5000.0)
+                .logarithmic(true)
+                .text("dots per second"),
+        );
+        ui.add(Slider::new(&mut airbrush.radius, 1.0..=200.0).text("radius"));
+        ui.add(Slider::new(&mut airbrush.falloff, 0.0..=1.0).text("falloff"));
+    }
+}
+
+impl App {
+    /// Sprays the dots for `dt` seconds around `pos`
+    pub fn spray(&mut self, pos: Location, px: u32, dt: f32) {
+        let draw = self.draw.at_loc(pos).px(px);
+        // uniform over the area at 0.5, more towards the center above that
+        let exponent = 0.5 + self.airbrush.falloff * 1.5;
+        self.airbrush.time += dt;
+        while self.airbrush.time >= STEP {
+            self.airbrush.time -= STEP;
+            let dots = self.airbrush.density * STEP + self.airbrush.carry;
+            self.airbrush.carry = dots.fract();
+            for _ in 0..dots as usize {
+                let rand = &mut self.airbrush.rand;
+                let angle = rand_f32(rand) * TAU;
+                let dist = self.airbrush.radius * rand_f32(rand).powf(exponent);
+                let x = pos.x as f32 + angle.cos() * dist;
+                let y = pos.y as f32 + angle.sin() * dist;
+                if x >= 0.0 && y >= 0.0 {
+                    self.set_px(draw.at(x as usize, y as usize));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,472 @@
+//! Frames for small animations: a timeline, onion skinning, playback, and GIF/APNG import and export
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use egui::*;
+use image::{
+    codecs::{gif::GifDecoder, png::PngDecoder},
+    AnimationDecoder,
+};
+use micro_ndarray::Array;
+
+use crate::{
+    blend::BlendMode,
+    dialog::DialogAction,
+    io::{from_rgba_image, to_rgba_image},
+    popup::lerp_color,
+    App,
+};
+
+pub struct AnimationFrame {
+    pub image: Array<Color32, 2>,
+    /// how long the frame is shown, in milliseconds
+    pub duration: u32,
+}
+
+/// Shows the frames around the current one faintly over the canvas
+pub struct OnionSkin {
+    pub enabled: bool,
+    pub before: usize,
+    pub after: usize,
+    pub opacity: f32,
+}
+
+impl Default for OnionSkin {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            before: 1,
+            after: 0,
+            opacity: 0.3,
+        }
+    }
+}
+
+pub struct Animation {
+    /// the current frame is edited in `App::image`, its entry here is only updated when switching frames
+    pub frames: Vec<AnimationFrame>,
+    pub current: usize,
+    pub onion: OnionSkin,
+    pub show_timeline: bool,
+    /// how long the current frame has been shown, in seconds, if playing
+    playing: Option<f32>,
+    /// the onion skin overlay, None if it has to be made again
+    onion_tex: Option<TextureHandle>,
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Self {
+            frames: vec![AnimationFrame {
+                image: Array::new_with([0, 0], Color32::TRANSPARENT),
+                duration: 100,
+            }],
+            current: 0,
+            onion: OnionSkin::default(),
+            show_timeline: false,
+            playing: None,
+            onion_tex: None,
+        }
+    }
+}
+
+/// Makes the image exactly `size` big, cutting it off or filling it with transparency
+fn fit(image: &Array<Color32, 2>, size: [usize; 2]) -> Array<Color32, 2> {
+    if image.size() == size {
+        return image.clone();
+    }
+    Array::new_by_enumeration(size, |i| {
+        let (x, y) = (i % size[0], i / size[0]);
+        image.get([x, y]).copied().unwrap_or(Color32::TRANSPARENT)
+    })
+}
+
+impl Animation {
+    /// Uses other frames, the image has to be set to the current one afterwards
+    pub fn replace(&mut self, frames: Vec<AnimationFrame>, current: usize) {
+        self.current = current.min(frames.len().saturating_sub(1));
+        self.frames = frames;
+        self.playing = None;
+        self.onion_tex = None;
+    }
+
+    /// The bar under the canvas with the frames and the playback and onion skin options
+    pub fn timeline(app: &mut App, ui: &mut Ui) {
+        if let Some(ref mut time) = app.animation.playing {
+            *time += ui.input(|inp| inp.stable_dt).min(0.1);
+            let duration = app.animation.frames[app.animation.current].duration as f32 / 1000.0;
+            if *time >= duration {
+                *time -= duration;
+                let next = (app.animation.current + 1) % app.animation.frames.len();
+                app.select_frame(next);
+            }
+        }
+
+        ui.horizontal(|ui| {
+            let playing = app.animation.playing.is_some();
+            if ui.button(if playing { "Stop" } else { "Play" }).clicked() {
+                app.animation.playing = if playing { None } else { Some(0.0) };
+            }
+            ui.separator();
+            if ui.button("New").clicked() {
+                app.insert_frame(Array::new_with(app.image.size(), Color32::TRANSPARENT));
+            }
+            if ui.button("Duplicate").clicked() {
+                app.insert_frame(app.image.clone());
+            }
+            let count = app.animation.frames.len();
+            let current = app.animation.current;
+            if ui.add_enabled(count > 1, Button::new("Delete")).clicked() {
+                app.animation.frames.remove(current);
+                // the image still has the deleted frame, so it mustn't be stored over the next one
+                let next = current.min(count - 2);
+                app.animation.current = next;
+                app.show_frame(next);
+            }
+            if ui
+                .add_enabled(current > 0, Button::new("Move left"))
+                .clicked()
+            {
+                app.animation.frames.swap(current, current - 1);
+                app.animation.current -= 1;
+                app.animation.onion_tex = None;
+            }
+            if ui
+                .add_enabled(current + 1 < count, Button::new("Move right"))
+                .clicked()
+            {
+                app.animation.frames.swap(current, current + 1);
+                app.animation.current += 1;
+                app.animation.onion_tex = None;
+            }
+            ui.separator();
+            ui.label("Duration");
+            ui.add(
+                DragValue::new(&mut app.animation.frames[current].duration)
+                    .clamp_range(10..=10000)
+                    .suffix(" ms"),
+            );
+            ui.separator();
+            let onion = &mut app.animation.onion;
+            let mut changed = ui.checkbox(&mut onion.enabled, "Onion skin").changed();
+            if onion.enabled {
+                changed |= ui
+                    .add(
+                        DragValue::new(&mut onion.before)
+                            .clamp_range(0..=5)
+                            .prefix("before: "),
+                    )
+                    .changed();
+                changed |= ui
+                    .add(
+                        DragValue::new(&mut onion.after)
+                            .clamp_range(0..=5)
+                            .prefix("after: "),
+                    )
+                    .changed();
+                changed |= ui
+                    .add(Slider::new(&mut onion.opacity, 0.05..=1.0).text("opacity"))
+                    .changed();
+            }
+            if changed {
+                app.animation.onion_tex = None;
+            }
+        });
+        ScrollArea::horizontal().show(ui, |ui| {
+            ui.horizontal(|ui| {
+                for i in 0..app.animation.frames.len() {
+                    let text = format!("{}\n{} ms", i + 1, app.animation.frames[i].duration);
+                    if ui
+                        .selectable_label(i == app.animation.current, text)
+                        .clicked()
+                        && i != app.animation.current
+                    {
+                        app.select_frame(i);
+                    }
+                }
+            });
+        });
+    }
+
+    /// Draws the frames around the current one over the canvas
+    pub fn render_onion(app: &mut App, painter: &Painter, canvas: Rect) {
+        let onion = &app.animation.onion;
+        if !onion.enabled || app.animation.frames.len() < 2 {
+            return;
+        }
+        let size = app.image.size();
+        let outdated = app
+            .animation
+            .onion_tex
+            .as_ref()
+            .is_none_or(|tex| tex.size() != size);
+        if outdated {
+            let current = app.animation.current;
+            let count = app.animation.frames.len();
+            // red for the frames before, blue for the ones after, the closest ones are the strongest
+            let mut neighbors = Vec::new();
+            for k in (1..=onion.before.min(current)).rev() {
+                let fade = 1.0 - (k - 1) as f32 / onion.before as f32;
+                neighbors.push((current - k, Color32::RED, fade));
+            }
+            for k in (1..=onion.after.min(count - 1 - current)).rev() {
+                let fade = 1.0 - (k - 1) as f32 / onion.after as f32;
+                neighbors.push((current + k, Color32::BLUE, fade));
+            }
+            let mut overlay = Array::new_with(size, Color32::TRANSPARENT);
+            for (i, tint, fade) in neighbors {
+                let frame = &app.animation.frames[i].image;
+                for ([x, y], px) in overlay.iter_mut() {
+                    if let Some(&c) = frame.get([x, y]) {
+                        let [.., a] = c.to_array();
+                        let tinted = lerp_color(c, tint.gamma_multiply(a as f32 / 255.0), 0.5);
+                        *px = BlendMode::Normal.blend(*px, tinted, onion.opacity * fade);
+                    }
+                }
+            }
+            let image = ColorImage {
+                size,
+                pixels: overlay.as_flattened().to_vec(),
+            };
+            app.animation.onion_tex = Some(painter.ctx().load_texture(
+                "onion",
+                image,
+                TextureOptions::NEAREST,
+            ));
+        }
+        if let Some(ref tex) = app.animation.onion_tex {
+            painter.image(
+                tex.id(),
+                canvas,
+                Rect::from_min_max(Pos2::ZERO, pos2(1.0, 1.0)),
+                Color32::WHITE,
+            );
+        }
+    }
+
+    pub fn menu(app: &mut App, ui: &mut Ui) {
+        ui.checkbox(&mut app.animation.show_timeline, "Show timeline");
+        ui.separator();
+        if ui.button("Import GIF or APNG...").clicked() {
+            app.file_dialog(false, DialogAction::ImportAnimation);
+            ui.close_menu();
+        }
+        if ui.button("Export animated GIF...").clicked() {
+            app.file_dialog(true, DialogAction::ExportGif);
+            ui.close_menu();
+        }
+        if ui.button("Export APNG...").clicked() {
+            app.file_dialog(true, DialogAction::ExportApng);
+            ui.close_menu();
+        }
+        if ui.button("Export PNG sequence...").clicked() {
+            app.file_dialog(true, DialogAction::ExportPngSequence);
+            ui.close_menu();
+        }
+    }
+}
+
+impl App {
+    /// Makes the current image the only frame, for when another file is opened
+    pub fn reset_animation(&mut self) {
+        let frame = AnimationFrame {
+            image: self.image.clone(),
+            duration: 100,
+        };
+        self.animation.replace(vec![frame], 0);
+    }
+
+    /// Changes the image and every other frame the same way, for canvas transforms like scaling or cropping,
+    /// so the frames keep the size of the canvas
+    pub fn transform_frames(
+        &mut self,
+        transform: impl Fn(&Array<Color32, 2>) -> Array<Color32, 2>,
+    ) {
+        let size = self.image.size();
+        let current = self.animation.current;
+        for (i, frame) in self.animation.frames.iter_mut().enumerate() {
+            if i != current {
+                frame.image = transform(&fit(&frame.image, size));
+            }
+        }
+        self.animation.onion_tex = None;
+        self.replace_image(transform(&self.image));
+    }
+
+    /// Stores the image in the current frame and shows another one
+    pub fn select_frame(&mut self, index: usize) {
+        let current = self.animation.current;
+        self.animation.frames[current].image = self.image.clone();
+        self.animation.current = index;
+        self.show_frame(index);
+    }
+
+    /// Puts a frame on the canvas, without storing the image first
+    fn show_frame(&mut self, index: usize) {
+        self.image = self.animation.frames[index].image.clone();
+        let [w, h] = self.image.size();
+        if self
+            .selection
+            .as_ref()
+            .is_some_and(|s| s.mask.size() != [w, h])
+        {
+            self.selection = None;
+        }
+        self.changes.all(Rect::from_min_max(
+            Pos2::ZERO,
+            Pos2::new(w as f32, h as f32),
+        ));
+        self.animation.onion_tex = None;
+    }
+
+    /// Adds a frame after the current one and shows it
+    fn insert_frame(&mut self, image: Array<Color32, 2>) {
+        let current = self.animation.current;
+        let duration = self.animation.frames[current].duration;
+        self.animation.frames[current].image = self.image.clone();
+        self.animation
+            .frames
+            .insert(current + 1, AnimationFrame { image, duration });
+        self.animation.current = current + 1;
+        self.show_frame(current + 1);
+    }
+
+    /// Every frame with the current image, all the size of the canvas
+    pub fn animation_frames(&self) -> Vec<(Array<Color32, 2>, u32)> {
+        let size = self.image.size();
+        self.animation
+            .frames
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| {
+                let image = if i == self.animation.current {
+                    &self.image
+                } else {
+                    &frame.image
+                };
+                (fit(image, size), frame.duration)
+            })
+            .collect()
+    }
+
+    /// Replaces the frames with the ones from an animated GIF or PNG
+    pub fn import_animation(&mut self, path: &Path) {
+        let file = match File::open(path) {
+            Ok(file) => BufReader::new(file),
+            Err(e) => {
+                println!("Unable to import this animation: {e}");
+                return;
+            }
+        };
+        let frames = match image::ImageFormat::from_path(path) {
+            Ok(image::ImageFormat::Png) => PngDecoder::new(file)
+                .and_then(|decoder| decoder.apng().into_frames().collect_frames()),
+            _ => GifDecoder::new(file).and_then(|decoder| decoder.into_frames().collect_frames()),
+        };
+        let frames = match frames {
+            Ok(frames) if !frames.is_empty() => frames,
+            Ok(_) => {
+                println!("Unable to import this animation: it has no frames.");
+                return;
+            }
+            Err(e) => {
+                println!("Unable to import this animation: {e}");
+                return;
+            }
+        };
+        let frames = frames
+            .into_iter()
+            .map(|frame| {
+                let (numerator, denominator) = frame.delay().numer_denom_ms();
+                AnimationFrame {
+                    duration: (numerator / denominator.max(1)).max(10),
+                    image: from_rgba_image(frame.buffer()),
+                }
+            })
+            .collect();
+        self.animation.replace(frames, 0);
+        // the canvas keeps the size of the frames instead of following the window
+        self.canvas_size = Some(self.animation.frames[0].image.size());
+        self.show_frame(0);
+    }
+
+    pub fn export_gif(&mut self, path: &Path) {
+        let [w, h] = self.image.size();
+        let (Ok(width), Ok(height)) = (u16::try_from(w), u16::try_from(h)) else {
+            println!(
+                "Unable to export this animation: GIFs can be at most 65535 pixels wide and high."
+            );
+            return;
+        };
+        if let Err(e) = write_gif(path, width, height, self.animation_frames()) {
+            println!("Unable to export this animation: {e}");
+        }
+    }
+
+    pub fn export_apng(&mut self, path: &Path) {
+        let [w, h] = self.image.size();
+        if let Err(e) = write_apng(path, w as u32, h as u32, self.animation_frames()) {
+            println!("Unable to export this animation: {e}");
+        }
+    }
+
+    /// Saves every frame as its own PNG, numbered after the file name
+    pub fn export_png_sequence(&mut self, path: &Path) {
+        let stem = path.with_extension("");
+        let frames = self.animation_frames();
+        let digits = frames.len().to_string().len().max(3);
+        for (i, (image, _)) in frames.into_iter().enumerate() {
+            let name = format!("{}_{:0digits$}.png", stem.display(), i + 1);
+            if let Err(e) = to_rgba_image(&image).save(&name) {
+                println!("Unable to export frame {}: {e}", i + 1);
+                return;
+            }
+        }
+    }
+}
+
+fn write_gif(
+    path: &Path,
+    width: u16,
+    height: u16,
+    frames: Vec<(Array<Color32, 2>, u32)>,
+) -> Result<(), gif::EncodingError> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = gif::Encoder::new(file, width, height, &[])?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+    for (image, duration) in frames {
+        let mut rgba = to_rgba_image(&image).into_raw();
+        // each frame gets its own palette, transparent pixels are kept
+        let mut frame = gif::Frame::from_rgba_speed(width, height, &mut rgba, 10);
+        // in hundredths of a second
+        frame.delay = (duration / 10).clamp(1, u16::MAX as u32) as u16;
+        frame.dispose = gif::DisposalMethod::Background;
+        encoder.write_frame(&frame)?;
+    }
+    Ok(())
+}
+
+fn write_apng(
+    path: &Path,
+    width: u32,
+    height: u32,
+    frames: Vec<(Array<Color32, 2>, u32)>,
+) -> Result<(), png::EncodingError> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0)?;
+    // each frame replaces the last one, so transparent pixels don't show the one before
+    encoder.set_blend_op(png::BlendOp::Source)?;
+    let mut writer = encoder.write_header()?;
+    for (image, duration) in frames {
+        writer.set_frame_delay(duration.min(u16::MAX as u32) as u16, 1000)?;
+        writer.write_image_data(&to_rgba_image(&image))?;
+    }
+    writer.finish()
+}
@@ -0,0 +1,129 @@
+//! Blend modes, which decide how a painted color combines with the color below it
+
+use egui::*;
+
+use crate::App;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    Add,
+    Difference,
+    Color,
+    Luminosity,
+}
+
+use BlendMode::*;
+
+impl BlendMode {
+    pub const ALL: [BlendMode; 10] = [
+        Normal, Multiply, Screen, Overlay, Darken, Lighten, Add, Difference, Color, Luminosity,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Normal => "Normal",
+            Multiply => "Multiply",
+            Screen => "Screen",
+            Overlay => "Overlay",
+            Darken => "Darken",
+            Lighten => "Lighten",
+            Add => "Add",
+            Difference => "Difference",
+            Color => "Color",
+            Luminosity => "Luminosity",
+        }
+    }
+
+    /// The mixed color of `base` (below) and `top` (rgb 0..1, not premultiplied), ignoring alpha
+    fn mix(self, base: [f32; 3], top: [f32; 3]) -> [f32; 3] {
+        let channels = |f: fn(f32, f32) -> f32| [0, 1, 2].map(|i| f(base[i], top[i]));
+        match self {
+            Normal => top,
+            Multiply => channels(|b, t| b * t),
+            Screen => channels(|b, t| b + t - b * t),
+            Overlay => channels(|b, t| {
+                if b <= 0.5 {
+                    2.0 * b * t
+                } else {
+                    1.0 - 2.0 * (1.0 - b) * (1.0 - t)
+                }
+            }),
+            Darken => channels(f32::min),
+            Lighten => channels(f32::max),
+            Add => channels(|b, t| (b + t).min(1.0)),
+            Difference => channels(|b, t| (b - t).abs()),
+            // the hue and saturation of the top, the brightness of the base
+            Color => set_lum(top, lum(base)),
+            Luminosity => set_lum(base, lum(top)),
+        }
+    }
+
+    /// Composites `top` over `base` with this mode. `opacity` (0..1) scales the alpha of `top`.
+    /// Works for any alpha on both sides, so it can be used for layers too.
+    pub fn blend(self, base: Color32, top: Color32, opacity: f32) -> Color32 {
+        let unmultiplied = |c: Color32| {
+            let [r, g, b, a] = c.to_srgba_unmultiplied().map(|c| c as f32 / 255.0);
+            ([r, g, b], a)
+        };
+        let (cb, ab) = unmultiplied(base);
+        let (cs, as_) = unmultiplied(top);
+        let as_ = as_ * opacity.clamp(0.0, 1.0);
+        if as_ <= 0.0 {
+            return base;
+        }
+        // the common case, which also doesn't lose precision
+        if self == Normal && as_ >= 1.0 {
+            return top;
+        }
+        // the W3C compositing formula: where the base is transparent, the top shows unmixed
+        let mixed = self.mix(cb, cs);
+        let a = as_ + ab * (1.0 - as_);
+        let c = [0, 1, 2].map(|i| {
+            let cs = (1.0 - ab) * cs[i] + ab * mixed[i];
+            // premultiplied, then divided again by the new alpha
+            (as_ * cs + (1.0 - as_) * ab * cb[i]) / a
+        });
+        let [r, g, b] = c.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+        Color32::from_rgba_unmultiplied(r, g, b, (a * 255.0).round() as u8)
+    }
+
+    pub fn menu(app: &mut App, ui: &mut Ui) {
+        ComboBox::from_label("Blend mode")
+            .selected_text(app.blend_mode.name())
+            .show_ui(ui, |ui| {
+                for mode in Self::ALL {
+                    ui.selectable_value(&mut app.blend_mode, mode, mode.name());
+                }
+            });
+        ui.add(Slider::new(&mut app.opacity, 0.0..=1.0).text("opacity"))
+            .on_hover_text("The most one stroke covers what is below it");
+    }
+}
+
+fn lum([r, g, b]: [f32; 3]) -> f32 {
+    0.3 * r + 0.59 * g + 0.11 * b
+}
+
+/// Changes the luminosity of a color while keeping its hue, clipping it back into 0..1
+fn set_lum(c: [f32; 3], l: f32) -> [f32; 3] {
+    let d = l - lum(c);
+    let c = c.map(|c| c + d);
+    let l = lum(c);
+    let min = c[0].min(c[1]).min(c[2]);
+    let max = c[0].max(c[1]).max(c[2]);
+    c.map(|c| {
+        if min < 0.0 {
+            l + (c - l) * l / (l - min)
+        } else if max > 1.0 {
+            l + (c - l) * (1.0 - l) / (max - l)
+        } else {
+            c
+        }
+    })
+}
@@ -0,0 +1,150 @@
+//! The clone stamp and healing brush, which paint with pixels copied from another part of the image
+
+use egui::*;
+
+use crate::{
+    draw::{DrawParams, Location},
+    App,
+};
+
+pub struct CloneStamp {
+    /// keep the same offset between strokes, instead of starting at the source again each stroke
+    pub aligned: bool,
+    /// how much of the brush radius fades out
+    pub softness: f32,
+    /// set with alt + click
+    pub source: Option<Location>,
+    /// from where the brush is to where it copies from, set when a stroke starts
+    offset: Option<[isize; 2]>,
+}
+
+impl Default for CloneStamp {
+    fn default() -> Self {
+        Self {
+            aligned: true,
+            softness: 0.5,
+            source: None,
+            offset: None,
+        }
+    }
+}
+
+impl CloneStamp {
+    pub fn menu(app: &mut App, ui: &mut Ui) {
+        let clone = &mut app.clone_stamp;
+        ui.checkbox(&mut clone.aligned, "Aligned")
+            .on_hover_text("Every stroke continues with the same offset to the source");
+        ui.add(Slider::new(&mut clone.softness, 0.0..=1.0).text("softness"));
+        ui.label("Alt + click sets the source");
+    }
+
+    /// Sets the point to copy from
+    pub fn set_source(&mut self, pos: Location) {
+        self.source = Some(pos);
+        self.offset = None;
+    }
+
+    /// Called when a stroke starts at `pos`
+    pub fn start(&mut self, pos: Location) {
+        if self.offset.is_none() || !self.aligned {
+            self.offset = self
+                .source
+                .map(|s| [s.x as isize - pos.x as isize, s.y as isize - pos.y as isize]);
+        }
+    }
+
+    /// Marks where the brush currently copies from (or will, once a stroke starts)
+    pub fn render(&self, app: &App, painter: &Painter, canvas: Rect, hover: Option<Location>) {
+        let source = match (self.offset, hover) {
+            (Some([dx, dy]), Some(pos)) => pos.offset(dx, dy),
+            _ => match self.source {
+                Some(source) => source,
+                None => return,
+            },
+        };
+        let center = canvas.min + vec2(app.to_point(source.x), app.to_point(source.y));
+        let radius = app.to_point(app.draw.size.max(1));
+        painter.circle_stroke(center, radius, Stroke::new(2.0, Color32::BLACK));
+        painter.circle_stroke(center, radius, Stroke::new(1.0, Color32::WHITE));
+        painter.line_segment(
+            [center - vec2(4.0, 0.0), center + vec2(4.0, 0.0)],
+            Stroke::new(1.0, Color32::WHITE),
+        );
+        painter.line_segment(
+            [center - vec2(0.0, 4.0), center + vec2(0.0, 4.0)],
+            Stroke::new(1.0, Color32::WHITE),
+        );
+    }
+}
+
+impl App {
+    pub fn clone_dot(&mut self, draw: DrawParams) {
+        self.clone_or_heal(draw, false);
+    }
+
+    pub fn heal_dot(&mut self, draw: DrawParams) {
+        self.clone_or_heal(draw, true);
+    }
+
+    /// Copies a round dab from the source. Healing keeps the texture of the source,
+    /// but shifts its colors to match the area around the brush.
+    fn clone_or_heal(&mut self, draw: DrawParams, heal: bool) {
+        let Some([ox, oy]) = self.clone_stamp.offset else {
+            return;
+        };
+        let size = draw.size.max(1) as isize;
+        let radius = size as f32;
+        let hard = radius * (1.0 - self.clone_stamp.softness);
+        // copied from the image before the stroke, so the stroke doesn't copy itself
+        let base = self.stroke_buffer().base();
+        let mut dab = Vec::new();
+        for dy in -size + 1..size {
+            for dx in -size + 1..size {
+                let dist = ((dx * dx + dy * dy) as f32).sqrt();
+                if dist >= radius {
+                    continue;
+                }
+                let dst = draw.loc.offset(dx, dy);
+                let src = dst.offset(ox, oy);
+                let (Some(&src_col), Some(&dst_col)) =
+                    (base.get([src.x, src.y]), base.get([dst.x, dst.y]))
+                else {
+                    continue;
+                };
+                let strength = if dist <= hard {
+                    1.0
+                } else {
+                    1.0 - (dist - hard) / (radius - hard)
+                };
+                dab.push((dst, src_col, dst_col, strength));
+            }
+        }
+        if dab.is_empty() {
+            return;
+        }
+
+        // how much the colors of the source have to change to fit in at the destination
+        let shift = if heal {
+            let mean = |colors: &mut dyn Iterator<Item = Color32>| {
+                let mut sum = [0.0; 3];
+                for c in colors {
+                    let [r, g, b, _] = c.to_srgba_unmultiplied();
+                    sum = [sum[0] + r as f32, sum[1] + g as f32, sum[2] + b as f32];
+                }
+                sum.map(|c| c / dab.len() as f32)
+            };
+            let src = mean(&mut dab.iter().map(|d| d.1));
+            let dst = mean(&mut dab.iter().map(|d| d.2));
+            [0, 1, 2].map(|i| dst[i] - src[i])
+        } else {
+            [0.0; 3]
+        };
+
+        for (Location { x, y }, src_col, _, strength) in dab {
+            let [r, g, b, a] = src_col.to_srgba_unmultiplied();
+            let [r, g, b] = [(r, shift[0]), (g, shift[1]), (b, shift[2])]
+                .map(|(c, s)| (c as f32 + s).round().clamp(0.0, 255.0) as u8);
+            self.blend_px_unchecked(x, y, Color32::from_rgba_unmultiplied(r, g, b, a), strength);
+        }
+    }
+}
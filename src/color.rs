@@ -1,68 +1,21 @@
-use egui::*;
-
-use crate::App;
-
-#[derive(Clone, Copy, PartialEq, Eq)]
-pub enum DrawColor {
-    Black,
-    White,
-    Red,
-    Green,
-    Blue,
-    Yellow,
-    Orange,
-    Brown,
-    Aqua,
-    Purple,
-}
-
-use DrawColor::*;
-
-impl DrawColor {
-    pub fn menu(app: &mut App, ui: &mut Ui) {
-        let mut clicked = false;
-        // rust does not have reflection at the moment so this looks a bit ugly and repetitive
-        // adds button to the menu
-        clicked |= ui.radio_value(&mut app.color, Black, "Black").clicked();
-        clicked |= ui.radio_value(&mut app.color, White, "White").clicked();
-        clicked |= ui.radio_value(&mut app.color, Red, "Red").clicked();
-        clicked |= ui.radio_value(&mut app.color, Green, "Green").clicked();
-        clicked |= ui.radio_value(&mut app.color, Blue, "Blue").clicked();
-        clicked |= ui.radio_value(&mut app.color, Yellow, "Yellow").clicked();
-        clicked |= ui.radio_value(&mut app.color, Orange, "Orange").clicked();
-        clicked |= ui.radio_value(&mut app.color, Brown, "Brown").clicked();
-        clicked |= ui.radio_value(&mut app.color, Aqua, "Aqua").clicked();
-        clicked |= ui.radio_value(&mut app.color, Purple, "Purple").clicked();
-        // if any have been clicked, change the color to it
-        if clicked {
-            app.draw.px = app.color.into_color();
-        }
-    }
-}
-
-pub trait ColorConvert {
+pub trait ColorConvert: Sized {
     fn into_color(self) -> u32;
     fn into_colorf(self) -> [f32; 3];
-}
 
-impl ColorConvert for DrawColor {
-    fn into_color(self) -> u32 {
-        match self {
-            Black => 0,
-            White => 0xffffff,
-            Red => 0xff0000,
-            Green => 0x00ff00,
-            Blue => 0x0000ff,
-            Yellow => 0xffff00,
-            Orange => 0xff8000,
-            Brown => 0x654321,
-            Aqua => 0x00ffff,
-            Purple => 0xff00ff,
-        }
+    fn into_hsv(self) -> Hsv {
+        Hsv(rgb_to_hsv(self.into_colorf()))
     }
 
-    fn into_colorf(self) -> [f32; 3] {
-        self.into_color().into_colorf()
+    fn into_hsl(self) -> Hsl {
+        Hsl(rgb_to_hsl(self.into_colorf()))
+    }
+
+    fn into_lab(self) -> Lab {
+        Lab(rgb_to_lab(self.into_colorf()))
+    }
+
+    fn into_oklch(self) -> Oklch {
+        Oklch(rgb_to_oklch(self.into_colorf()))
     }
 }
 
@@ -82,9 +35,8 @@ impl ColorConvert for u32 {
 
 impl ColorConvert for [f32; 3] {
     fn into_color(self) -> u32 {
-        let r = (self[0] * 255.0) as u32;
-        let g = (self[1] * 255.0) as u32;
-        let b = (self[2] * 255.0) as u32;
+        // rounded so converting back and forth doesn't make colors darker
+        let [r, g, b] = self.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u32);
         (r << 16) + (g << 8) + b
     }
 
@@ -92,3 +44,324 @@ impl ColorConvert for [f32; 3] {
         self
     }
 }
+
+/// hue (0..360), saturation and value (0..1)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Hsv(pub [f32; 3]);
+
+/// hue (0..360), saturation and lightness (0..1)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Hsl(pub [f32; 3]);
+
+/// CIELAB: L (0..100), a and b (about -128..128)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Lab(pub [f32; 3]);
+
+/// OKLab in polar form: lightness (0..1), chroma (0..about 0.4) and hue (0..360)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Oklch(pub [f32; 3]);
+
+macro_rules! color_space {
+    ($space:ident, $to_rgb:ident) => {
+        impl ColorConvert for $space {
+            fn into_color(self) -> u32 {
+                self.into_colorf().into_color()
+            }
+
+            fn into_colorf(self) -> [f32; 3] {
+                $to_rgb(self.0)
+            }
+        }
+    };
+}
+
+color_space!(Hsv, hsv_to_rgb);
+color_space!(Hsl, hsl_to_rgb);
+color_space!(Lab, lab_to_rgb);
+color_space!(Oklch, oklch_to_rgb);
+
+/// rgb (0..1) to hue (0..360), saturation and value (0..1)
+pub fn rgb_to_hsv([r, g, b]: [f32; 3]) -> [f32; 3] {
+    let max = r.max(g).max(b);
+    let d = max - r.min(g).min(b);
+    if d == 0.0 {
+        return [0.0, 0.0, max];
+    }
+    [hue(r, g, b, max, d), d / max, max]
+}
+
+/// hue (0..360), saturation and value (0..1) to rgb (0..1)
+pub fn hsv_to_rgb([h, s, v]: [f32; 3]) -> [f32; 3] {
+    let c = v * s;
+    from_hue(h, c, v - c)
+}
+
+/// rgb (0..1) to hue (0..360), saturation and lightness (0..1)
+pub fn rgb_to_hsl([r, g, b]: [f32; 3]) -> [f32; 3] {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.0;
+    let d = max - min;
+    if d == 0.0 {
+        return [0.0, 0.0, l];
+    }
+    let s = d / (1.0 - (2.0 * l - 1.0).abs());
+    [hue(r, g, b, max, d), s, l]
+}
+
+/// hue (0..360), saturation and lightness (0..1) to rgb (0..1)
+pub fn hsl_to_rgb([h, s, l]: [f32; 3]) -> [f32; 3] {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    from_hue(h, c, l - c / 2.0)
+}
+
+/// the hue of an rgb color in degrees, given its max channel and its max - min
+fn hue(r: f32, g: f32, b: f32, max: f32, d: f32) -> f32 {
+    let h = if max == r {
+        (g - b) / d
+    } else if max == g {
+        (b - r) / d + 2.0
+    } else {
+        (r - g) / d + 4.0
+    };
+    (h * 60.0).rem_euclid(360.0)
+}
+
+/// rgb from a hue, chroma and the amount to add to every channel
+fn from_hue(h: f32, c: f32, m: f32) -> [f32; 3] {
+    let h = h.rem_euclid(360.0) / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let [r, g, b] = match h as usize {
+        0 => [c, x, 0.0],
+        1 => [x, c, 0.0],
+        2 => [0.0, c, x],
+        3 => [0.0, x, c],
+        4 => [x, 0.0, c],
+        _ => [c, 0.0, x],
+    };
+    [r + m, g + m, b + m]
+}
+
+/// sRGB channel (0..1) to linear light
+pub fn to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// linear light to sRGB channel (0..1)
+pub fn from_linear(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// CIELAB (L 0..100, a and b about -128..128, D65 white) to rgb (0..1, clamped)
+pub fn lab_to_rgb([l, a, b]: [f32; 3]) -> [f32; 3] {
+    let fy = (l + 16.0) / 116.0;
+    let fx = fy + a / 500.0;
+    let fz = fy - b / 200.0;
+    let f_inv = |t: f32| {
+        if t > 6.0 / 29.0 {
+            t * t * t
+        } else {
+            3.0 * (6.0f32 / 29.0).powi(2) * (t - 4.0 / 29.0)
+        }
+    };
+    let [x, y, z] = [0.95047 * f_inv(fx), f_inv(fy), 1.08883 * f_inv(fz)];
+    [
+        3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        -0.969266 * x + 1.8760108 * y + 0.041556 * z,
+        0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    ]
+    .map(|c| from_linear(c.clamp(0.0, 1.0)))
+}
+
+/// rgb (0..1) to CIELAB (L 0..100, a and b about -128..128, D65 white)
+pub fn rgb_to_lab(rgb: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb.map(to_linear);
+    let x = (0.4124564 * r + 0.3575761 * g + 0.1804375 * b) / 0.95047;
+    let y = 0.2126729 * r + 0.7151522 * g + 0.072175 * b;
+    let z = (0.0193339 * r + 0.119192 * g + 0.9503041 * b) / 1.08883;
+    let f = |t: f32| {
+        if t > (6.0f32 / 29.0).powi(3) {
+            t.cbrt()
+        } else {
+            t / (3.0 * (6.0f32 / 29.0).powi(2)) + 4.0 / 29.0
+        }
+    };
+    let [fx, fy, fz] = [f(x), f(y), f(z)];
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// rgb (0..1) to OKLab (L 0..1, a and b about -0.4..0.4)
+// the constants are the published ones, f32 just doesn't use all their digits
+#[allow(clippy::excessive_precision)]
+pub fn rgb_to_oklab(rgb: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb.map(to_linear);
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+    [
+        0.2104542553 * l + 0.793617785 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.428592205 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.808675766 * s,
+    ]
+}
+
+/// OKLab (L 0..1, a and b about -0.4..0.4) to rgb (0..1, clamped)
+#[allow(clippy::excessive_precision)]
+pub fn oklab_to_rgb([l, a, b]: [f32; 3]) -> [f32; 3] {
+    let l_ = (l + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+    let m_ = (l - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+    let s_ = (l - 0.0894841775 * a - 1.291485548 * b).powi(3);
+    [
+        4.0767416621 * l_ - 3.3077115913 * m_ + 0.2309699292 * s_,
+        -1.2684380046 * l_ + 2.6097574011 * m_ - 0.3413193965 * s_,
+        -0.0041960863 * l_ - 0.7034186147 * m_ + 1.707614701 * s_,
+    ]
+    .map(|c| from_linear(c.clamp(0.0, 1.0)))
+}
+
+/// rgb (0..1) to OKLCH (L 0..1, chroma 0..about 0.4, hue 0..360)
+pub fn rgb_to_oklch(rgb: [f32; 3]) -> [f32; 3] {
+    let [l, a, b] = rgb_to_oklab(rgb);
+    [l, a.hypot(b), b.atan2(a).to_degrees().rem_euclid(360.0)]
+}
+
+/// OKLCH (L 0..1, chroma 0..about 0.4, hue 0..360) to rgb (0..1, clamped)
+pub fn oklch_to_rgb([l, c, h]: [f32; 3]) -> [f32; 3] {
+    let h = h.to_radians();
+    oklab_to_rgb([l, c * h.cos(), c * h.sin()])
+}
+
+/// Parses "#rrggbb", "rrggbb", "#rgb" or "rgb(r, g, b)" (0..255 each)
+pub fn parse_color(text: &str) -> Option<u32> {
+    let text = text.trim();
+    if let Some(args) = text
+        .strip_prefix("rgb(")
+        .and_then(|args| args.strip_suffix(')'))
+    {
+        let channels: Vec<u8> = args
+            .split(',')
+            .map(|c| c.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        let [r, g, b] = channels[..] else {
+            return None;
+        };
+        return Some((r as u32) << 16 | (g as u32) << 8 | b as u32);
+    }
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    // from_str_radix would take a sign too
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let color = u32::from_str_radix(hex, 16).ok()?;
+    match hex.len() {
+        6 => Some(color),
+        // every digit is doubled: #abc is #aabbcc
+        3 => Some(
+            (0..3)
+                .map(|i| ((color >> (i * 4) & 0xf) * 0x11) << (i * 8))
+                .sum(),
+        ),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Colors spread over the whole rgb cube, including black, white and the primaries
+    fn colors() -> impl Iterator<Item = u32> {
+        let steps = (0..=255).step_by(17);
+        steps.clone().flat_map(move |r| {
+            let steps = steps.clone();
+            steps
+                .clone()
+                .flat_map(move |g| steps.clone().map(move |b| r << 16 | g << 8 | b))
+        })
+    }
+
+    fn assert_close(a: u32, b: u32, tolerance: u32) {
+        for shift in [16, 8, 0] {
+            let diff = (a >> shift & 0xff).abs_diff(b >> shift & 0xff);
+            assert!(diff <= tolerance, "{a:06x} became {b:06x}");
+        }
+    }
+
+    #[test]
+    fn hsv_and_hsl_round_trip() {
+        for color in colors() {
+            assert_eq!(color.into_hsv().into_color(), color);
+            assert_eq!(color.into_hsl().into_color(), color);
+        }
+    }
+
+    #[test]
+    fn lab_round_trip() {
+        for color in colors() {
+            assert_eq!(color.into_lab().into_color(), color);
+        }
+    }
+
+    #[test]
+    fn oklch_round_trip() {
+        for color in colors() {
+            assert_close(color, color.into_oklch().into_color(), 1);
+        }
+    }
+
+    #[test]
+    fn known_values() {
+        let [h, s, v] = 0xff8000.into_hsv().0;
+        assert_eq!([h.round(), s, v], [30.0, 1.0, 1.0]);
+        let [l, a, b] = 0xffffff.into_lab().0;
+        assert!((l - 100.0).abs() < 0.01 && a.abs() < 0.01 && b.abs() < 0.01);
+        assert!(0x000000.into_oklch().0[0].abs() < 1e-6);
+    }
+
+    #[test]
+    fn parse_hex() {
+        assert_eq!(parse_color("#abc"), Some(0xaabbcc));
+        assert_eq!(parse_color("ABC"), Some(0xaabbcc));
+        assert_eq!(parse_color(" #12aBeF "), Some(0x12abef));
+        assert_eq!(parse_color("000000"), Some(0));
+    }
+
+    #[test]
+    fn parse_rgb() {
+        assert_eq!(parse_color("rgb(255, 128, 0)"), Some(0xff8000));
+        assert_eq!(parse_color("rgb(0,0,0)"), Some(0));
+    }
+
+    #[test]
+    fn parse_bad_input() {
+        for text in [
+            "",
+            "#",
+            "#ab",
+            "#abcd",
+            "#1234567",
+            "+abc",
+            "#+abc",
+            "-12345",
+            "#ggg",
+            "#12 345",
+            "rgb()",
+            "rgb(1, 2)",
+            "rgb(1, 2, 3, 4)",
+            "rgb(256, 0, 0)",
+            "rgb(-1, 0, 0)",
+            "rgb(1, 2, 3",
+            "hsl(0, 0, 0)",
+        ] {
+            assert_eq!(parse_color(text), None, "{text:?}");
+        }
+    }
+}
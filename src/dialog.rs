@@ -1,43 +1,108 @@
 use egui::*;
 
-use crate::App;
+use crate::{export::ExportDialog, palette::Palette, popup::Popup, App};
 
 pub enum DialogAction {
     Open,
     Save,
+    Export,
+    ExportIndexed,
+    LoadPalette,
+    SavePalette,
+    LoadMask,
+    SaveMask,
+    LoadPatternTile,
+    LoadFillPattern,
+    ImportAnimation,
+    ExportGif,
+    ExportApng,
+    ExportPngSequence,
 }
 
 impl App {
-    // handles Open
-    pub fn open_file(&mut self) {
-        let mut dialog = egui_file::FileDialog::open_file(None);
+    // opens a file dialog, the action is done once a file is picked
+    pub fn file_dialog(&mut self, save: bool, action: DialogAction) {
+        let mut dialog = if save {
+            egui_file::FileDialog::save_file(None)
+        } else {
+            egui_file::FileDialog::open_file(None)
+        };
         dialog.open();
-        self.dialog_action = Some(DialogAction::Open);
+        self.dialog_action = Some(action);
         self.dialog = Some(dialog);
     }
 
+    // handles Open
+    pub fn open_file(&mut self) {
+        self.file_dialog(false, DialogAction::Open);
+    }
+
     // handles Save and SaveAs
     pub fn save_file(&mut self, ask_name: bool) {
         if ask_name || self.filename.is_none() {
-            let mut dialog = egui_file::FileDialog::save_file(None);
-            dialog.open();
-            self.dialog_action = Some(DialogAction::Save);
-            self.dialog = Some(dialog);
+            self.file_dialog(true, DialogAction::Save);
         } else {
             self.save();
         }
     }
 
+    // handles Export indexed
+    pub fn export_indexed_file(&mut self) {
+        self.file_dialog(true, DialogAction::ExportIndexed);
+    }
+
     // called on app update to update dialogs too
     pub fn handle_dialogs(&mut self, ctx: &Context) {
         if let Some(ref mut dialog) = self.dialog {
             if dialog.show(ctx).selected() {
                 if let Some(file) = dialog.path() {
-                    self.filename = Some(file.to_str().expect("invalid file name").to_owned());
+                    let file = file.to_owned();
                     // do IO operations associated with dialog
                     match self.dialog_action.as_ref().unwrap() {
-                        DialogAction::Open => self.load(),
-                        DialogAction::Save => self.save(),
+                        DialogAction::Open => {
+                            self.filename =
+                                Some(file.to_str().expect("invalid file name").to_owned());
+                            self.load()
+                        }
+                        DialogAction::Save => {
+                            self.filename =
+                                Some(file.to_str().expect("invalid file name").to_owned());
+                            self.save()
+                        }
+                        // exporting doesn't change which file is being edited
+                        DialogAction::Export => {
+                            self.popup = Some(Popup::Export(ExportDialog::new(self, file)))
+                        }
+                        DialogAction::ExportIndexed => {
+                            if let Err(e) = self.save_indexed(&file) {
+                                println!("Unable to export this image: {e}");
+                            }
+                        }
+                        DialogAction::LoadPalette => match Palette::load(&file) {
+                            Ok(palette) => self.palette = palette,
+                            Err(e) => println!("Unable to load palette: {e}"),
+                        },
+                        DialogAction::LoadMask => {
+                            if let Err(e) = self.load_mask(&file) {
+                                println!("Unable to load mask: {e}");
+                            }
+                        }
+                        DialogAction::SaveMask => {
+                            if let Err(e) = self.save_mask(&file) {
+                                println!("Unable to save mask: {e}");
+                            }
+                        }
+                        DialogAction::LoadPatternTile => self.load_pattern_tile(&file),
+                        DialogAction::LoadFillPattern => self.load_fill_pattern(&file),
+                        DialogAction::ImportAnimation => self.import_animation(&file),
+                        DialogAction::ExportGif => self.export_gif(&file),
+                        DialogAction::ExportApng => self.export_apng(&file),
+                        DialogAction::ExportPngSequence => self.export_png_sequence(&file),
+                        DialogAction::SavePalette => {
+                            if let Err(e) = self.palette.save(&file) {
+                                println!("Unable to save palette: {e}");
+                            }
+                        }
                     };
                     self.dialog_action = None;
                     self.dialog = None;
@@ -79,6 +79,11 @@ impl DrawParams {
         }
     }
 
+    #[inline]
+    pub fn px(&self, px: u32) -> Self {
+        Self { px, ..*self }
+    }
+
     #[inline]
     pub fn at_loc_sized(&self, loc: Location, size: usize) -> Self {
         Self { loc, size, ..*self }
@@ -92,24 +97,35 @@ impl App {
             px,
             ..
         } = draw;
+        let col = Color32::from_rgb((px >> 16) as u8, (px >> 8) as u8, px as u8);
+        self.set_px_color(x, y, col);
+    }
+
+    /// Like [`Self::set_px`], but with any color, including transparent ones
+    pub fn set_px_color(&mut self, x: usize, y: usize, col: Color32) {
         let size = self.image.size();
         if x >= size[0] || y >= size[1] {
             return; // just ignore
         }
-        if self.effects.checkerboard && (x + y) % 2 == 0 {
-            return;
-        }
-        self.image[[x, y]] = Color32::from_rgb((px >> 16) as u8, (px >> 8) as u8, px as u8);
-        self.changes.push(x, y);
+        // mirrored copies may land outside of the image, so these are checked again
+        let points = self
+            .effects
+            .symmetry
+            .points(x, y, size)
+            .filter(|loc| loc.x < size[0] && loc.y < size[1])
+            .map(|loc| [loc.x, loc.y]);
+        self.stroke_pxs(points, col, 1.0);
     }
 
     /// Does not ignore pixels out of bounds, panic!s instead.
+    /// Does not apply symmetry either, but stays inside the selection.
     pub fn set_px_unchecked(&mut self, x: usize, y: usize, col: Color32) {
-        if self.effects.checkerboard && (x + y) % 2 == 0 {
-            return;
-        }
-        self.image[[x, y]] = col;
-        self.changes.push(x, y);
+        self.blend_px_unchecked(x, y, col, 1.0);
+    }
+
+    /// Like [`Self::set_px_unchecked`], but only paints with `strength` (0..1) of the flow
+    pub fn blend_px_unchecked(&mut self, x: usize, y: usize, col: Color32, strength: f32) {
+        self.stroke_pxs([[x, y]], col, strength);
     }
 
     /// Draws a dot of arbitrary size. This is one px for a size of 0, a plus for size 1, and a rectangle of side length (size - 1) * 2
@@ -216,28 +232,64 @@ impl App {
         // amount of radians between each corner if it were on a circle
         let angle_increment = PI * 2.0 / n as f32;
 
-        // start one corner after the starting point because we draw lines
-        let mut current_angle = angle_increment + begin_angle;
-
         // center
         let fx = draw.loc.x as f32;
         let fy = draw.loc.y as f32;
 
-        let mut last_x = begin_angle.sin() * radius_x;
-        let mut last_y = begin_angle.cos() * radius_y;
-        // loop over corners and draw a line from the last to the current
-        for _ in 0..n {
-            let new_x = current_angle.sin() * radius_x;
-            let new_y = current_angle.cos() * radius_y;
+        let corners: Vec<(f32, f32)> = (0..=n)
+            .map(|i| {
+                let angle = begin_angle + angle_increment * i as f32;
+                (fx + angle.sin() * radius_x, fy + angle.cos() * radius_y)
+            })
+            .collect();
+
+        // the inside first so the outline stays on top, with the fill pattern if there is one
+        if self.effects.fill_shapes {
+            self.fill_polygon(draw.px(self.secondary_px), &corners);
+        }
 
+        // loop over corners and draw a line from the last to the current
+        for pair in corners.windows(2) {
+            let [(last_x, last_y), (new_x, new_y)] = [pair[0], pair[1]];
             self.draw_line(
-                draw.at((fx + last_x) as usize, (fy + last_y) as usize),
-                draw.at((fx + new_x) as usize, (fy + new_y) as usize),
+                draw.at(last_x as usize, last_y as usize),
+                draw.at(new_x as usize, new_y as usize),
                 Self::draw_dot,
             );
-            last_x = new_x;
-            last_y = new_y;
-            current_angle += angle_increment;
+        }
+    }
+
+    /// Fills the inside of a closed polygon (the last corner is the same as the first) row by row
+    pub fn fill_polygon(&mut self, draw: DrawParams, corners: &[(f32, f32)]) {
+        let [w, h] = self.image.size();
+        let min_y = corners.iter().map(|c| c.1).fold(f32::INFINITY, f32::min);
+        let max_y = corners
+            .iter()
+            .map(|c| c.1)
+            .fold(f32::NEG_INFINITY, f32::max);
+        // clamped to the image, so huge shapes don't take forever
+        for y in min_y.max(0.0).ceil() as usize..=max_y.min(h as f32 - 1.0).floor() as usize {
+            let fy = y as f32;
+            // where the edges cross this row, every pair of crossings encloses the inside
+            let mut crossings: Vec<f32> = corners
+                .windows(2)
+                .filter(|edge| (edge[0].1 <= fy) != (edge[1].1 <= fy))
+                .map(|edge| {
+                    let ((x1, y1), (x2, y2)) = (edge[0], edge[1]);
+                    x1 + (fy - y1) / (y2 - y1) * (x2 - x1)
+                })
+                .collect();
+            crossings.sort_by(f32::total_cmp);
+            for pair in crossings.chunks_exact(2) {
+                let (start, end) = (pair[0].max(0.0), pair[1].min(w as f32 - 1.0));
+                if start > end {
+                    continue;
+                }
+                for x in start.ceil() as usize..=end.floor() as usize {
+                    let col = self.fill_color_at(x, y, draw.px);
+                    self.set_px_color(x, y, col);
+                }
+            }
         }
     }
 }
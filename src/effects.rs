@@ -1,29 +1,56 @@
-use std::time::SystemTime;
+use std::{collections::HashMap, time::SystemTime};
 
-use crate::App;
+use crate::{mode::Mode, pattern::Pattern, symmetry::Symmetry, App};
 
 pub struct Effects {
+    /// the seed `rand` was last reset to, the same seed always gives the same random results
+    pub seed: u64,
     rand: u64,
     pub randomize_size: bool,
-    pub checkerboard: bool,
+    /// which pixels each tool paints
+    pub patterns: HashMap<Mode, Pattern>,
+    pub fill_shapes: bool,
+    pub symmetry: Symmetry,
 }
 
 impl Default for Effects {
     fn default() -> Self {
+        let seed = SystemTime::UNIX_EPOCH.elapsed().unwrap().as_micros() as u64;
         Self {
-            rand: SystemTime::UNIX_EPOCH.elapsed().unwrap().as_micros() as u64,
+            seed,
+            rand: seed,
             randomize_size: false,
-            checkerboard: false,
+            patterns: HashMap::new(),
+            fill_shapes: false,
+            symmetry: Symmetry::default(),
         }
     }
 }
 
-// bad (but good-enough for this purpose) random number generator
-fn rand(rand: &mut u64) -> u8 {
-    let state = *rand;
-    let r = ((state & 0b1111) << 60) + ((state >> 60) & 0b1111);
-    *rand = state.rotate_right(1) ^ r;
-    r as u8
+// good-enough random number generator, seedable so results can be repeated
+// (splitmix64, the rotate and xor it used before repeated after a few hundred numbers)
+pub fn rand(rand: &mut u64) -> u8 {
+    (rand_u64(rand) >> 56) as u8
+}
+
+fn rand_u64(rand: &mut u64) -> u64 {
+    *rand = rand.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *rand;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// uniformly in 0..1
+pub fn rand_f32(rand: &mut u64) -> f32 {
+    (rand_u64(rand) >> 40) as f32 / (1u64 << 24) as f32
+}
+
+impl Effects {
+    /// Starts the random results over from the seed
+    pub fn reseed(&mut self) {
+        self.rand = self.seed;
+    }
 }
 
 impl App {
@@ -36,3 +63,29 @@ impl App {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_numbers() {
+        let (mut a, mut b) = (42, 42);
+        for _ in 0..1000 {
+            assert_eq!(rand(&mut a), rand(&mut b));
+            let f = rand_f32(&mut a);
+            assert_eq!(f, rand_f32(&mut b));
+            assert!((0.0..1.0).contains(&f));
+        }
+    }
+
+    #[test]
+    fn no_short_cycle() {
+        let mut state = 1;
+        let first: Vec<u8> = (0..64).map(|_| rand(&mut state)).collect();
+        for _ in 0..100_000 {
+            let next: Vec<u8> = (0..64).map(|_| rand(&mut state)).collect();
+            assert_ne!(first, next);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! The eraser tool, which paints the background color (or transparency) back in
+
+use egui::*;
+
+use crate::{
+    draw::{DrawParams, Location},
+    popup::lerp_color,
+    App,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EraserShape {
+    Square,
+    Round,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EraseTo {
+    /// the secondary color
+    Background,
+    Transparency,
+}
+
+pub struct Eraser {
+    pub size: usize,
+    pub shape: EraserShape,
+    pub erase_to: EraseTo,
+    /// how much of the radius is fully erased, the rest fades out
+    pub hardness: f32,
+    /// only erase pixels close to `color`
+    pub color_only: bool,
+    pub color: u32,
+    /// how far (per channel) a pixel may be from `color` to be erased
+    pub tolerance: u8,
+}
+
+impl Default for Eraser {
+    fn default() -> Self {
+        Self {
+            size: 10,
+            shape: EraserShape::Square,
+            erase_to: EraseTo::Background,
+            hardness: 1.0,
+            color_only: false,
+            color: 0x000000,
+            tolerance: 32,
+        }
+    }
+}
+
+impl Eraser {
+    pub fn menu(app: &mut App, ui: &mut Ui) {
+        let eraser = &mut app.eraser;
+        ui.add(Slider::new(&mut eraser.size, 1..=100).text("size"));
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut eraser.shape, EraserShape::Square, "Square");
+            ui.radio_value(&mut eraser.shape, EraserShape::Round, "Round");
+        });
+        ui.add(Slider::new(&mut eraser.hardness, 0.0..=1.0).text("hardness"));
+        ui.separator();
+        ui.radio_value(
+            &mut eraser.erase_to,
+            EraseTo::Background,
+            "Erase to the secondary color",
+        );
+        ui.radio_value(
+            &mut eraser.erase_to,
+            EraseTo::Transparency,
+            "Erase to transparency",
+        );
+        ui.separator();
+        ui.checkbox(&mut eraser.color_only, "Only erase one color");
+        if eraser.color_only {
+            ui.horizontal(|ui| {
+                ui.label("Color");
+                let [r, g, b] = [16, 8, 0].map(|shift| (eraser.color >> shift) as u8);
+                let mut rgb = [r, g, b];
+                if ui.color_edit_button_srgb(&mut rgb).changed() {
+                    let [r, g, b] = rgb.map(|c| c as u32);
+                    eraser.color = r << 16 | g << 8 | b;
+                }
+                if ui.button("Use primary").clicked() {
+                    eraser.color = app.draw.px;
+                }
+            });
+            ui.add(Slider::new(&mut app.eraser.tolerance, 0..=255).text("tolerance"));
+        }
+    }
+}
+
+impl App {
+    /// Erases around the location, with the eraser's own size (the one in `draw` is ignored)
+    pub fn erase_dot(&mut self, draw: DrawParams) {
+        let Location { x, y } = draw.loc;
+        let size = self.eraser.size as isize;
+        // the distance from the center where the edges start to fade
+        let radius = self.eraser.size as f32;
+        let hard = radius * self.eraser.hardness;
+        let target = match self.eraser.erase_to {
+            EraseTo::Background => {
+                let px = self.secondary_px;
+                Color32::from_rgb((px >> 16) as u8, (px >> 8) as u8, px as u8)
+            }
+            EraseTo::Transparency => Color32::TRANSPARENT,
+        };
+        for dy in -size + 1..size {
+            for dx in -size + 1..size {
+                let dist = match self.eraser.shape {
+                    EraserShape::Square => dx.abs().max(dy.abs()) as f32,
+                    EraserShape::Round => ((dx * dx + dy * dy) as f32).sqrt(),
+                };
+                if dist >= radius {
+                    continue;
+                }
+                let strength = if dist <= hard {
+                    1.0
+                } else {
+                    1.0 - (dist - hard) / (radius - hard)
+                };
+                let (px, py) = (x.wrapping_add_signed(dx), y.wrapping_add_signed(dy));
+                self.erase_px(px, py, target, strength);
+            }
+        }
+    }
+
+    /// Moves one pixel (and its mirrored copies) from where it was when the stroke started towards the target color.
+    /// Overlapping dabs don't add up, each pixel is erased as much as the strongest one over it.
+    fn erase_px(&mut self, x: usize, y: usize, target: Color32, strength: f32) {
+        let size = self.image.size();
+        if x >= size[0] || y >= size[1] {
+            return;
+        }
+        for Location { x, y } in self.effects.symmetry.points(x, y, size) {
+            if x >= size[0] || y >= size[1] {
+                continue;
+            }
+            if !self.pattern_allows(x, y) {
+                continue;
+            }
+            let selected = self.selection_coverage(x, y);
+            if selected == 0.0 {
+                continue;
+            }
+            let old = self.stroke_buffer().base()[[x, y]];
+            if self.eraser.color_only {
+                let [r, g, b, _] = old.to_srgba_unmultiplied();
+                let c = self.eraser.color;
+                let far = [r, g, b]
+                    .into_iter()
+                    .zip([(c >> 16) as u8, (c >> 8) as u8, c as u8])
+                    .any(|(a, b)| a.abs_diff(b) > self.eraser.tolerance);
+                if far {
+                    continue;
+                }
+            }
+            let coverage = self.stroke_buffer().cover(x, y, strength);
+            self.image[[x, y]] = lerp_color(old, target, coverage * selected);
+            self.changes.push(x, y);
+        }
+    }
+}
@@ -0,0 +1,457 @@
+//! Saving with options for each format, like the JPEG quality or how hard PNGs are compressed
+
+use std::{fs, path::PathBuf};
+
+use egui::*;
+use image::{
+    codecs::{
+        bmp::BmpEncoder,
+        png::{CompressionType, FilterType, PngEncoder},
+        qoi::QoiEncoder,
+        tga::TgaEncoder,
+    },
+    ColorType, ImageEncoder,
+};
+use jpeg_encoder::SamplingFactor;
+use micro_ndarray::Array;
+use webp::WebPConfig;
+
+use crate::App;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Png,
+    Jpeg,
+    WebP,
+    Qoi,
+    Tga,
+    Bmp,
+}
+
+use ExportFormat::*;
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 6] = [Png, Jpeg, WebP, Qoi, Tga, Bmp];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Png => "PNG",
+            Jpeg => "JPEG",
+            WebP => "WebP",
+            Qoi => "QOI",
+            Tga => "TGA",
+            Bmp => "BMP",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Png => "png",
+            Jpeg => "jpg",
+            WebP => "webp",
+            Qoi => "qoi",
+            Tga => "tga",
+            Bmp => "bmp",
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "png" => Some(Png),
+            "jpg" | "jpeg" => Some(Jpeg),
+            "webp" => Some(WebP),
+            "qoi" => Some(Qoi),
+            "tga" => Some(Tga),
+            "bmp" => Some(Bmp),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct ExportOptions {
+    pub png_compression: CompressionType,
+    pub png_filter: FilterType,
+    /// 1..=100
+    pub jpeg_quality: u8,
+    /// how much smaller than the image the colors are stored
+    pub jpeg_sampling: SamplingFactor,
+    pub webp_lossy: bool,
+    /// libwebp's near lossless level for lossless WebP, 100 keeps every pixel and lower ones change colors more
+    pub webp_near_lossless: u8,
+    /// 0..=100, for lossy WebP
+    pub webp_quality: u8,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            png_compression: CompressionType::Default,
+            png_filter: FilterType::Adaptive,
+            jpeg_quality: 90,
+            jpeg_sampling: SamplingFactor::R_4_2_0,
+            webp_lossy: false,
+            webp_near_lossless: 100,
+            webp_quality: 80,
+        }
+    }
+}
+
+impl ExportOptions {
+    /// The options for one format, returns true if any were changed
+    fn menu(&mut self, format: ExportFormat, ui: &mut Ui) -> bool {
+        let mut changed = false;
+        match format {
+            Png => {
+                ui.horizontal(|ui| {
+                    ui.label("Compression");
+                    for (compression, name) in [
+                        (CompressionType::Fast, "Fast"),
+                        (CompressionType::Default, "Default"),
+                        (CompressionType::Best, "Best"),
+                    ] {
+                        changed |= ui
+                            .radio_value(&mut self.png_compression, compression, name)
+                            .changed();
+                    }
+                });
+                ComboBox::from_label("Filter")
+                    .selected_text(format!("{:?}", self.png_filter))
+                    .show_ui(ui, |ui| {
+                        for filter in [
+                            FilterType::Adaptive,
+                            FilterType::NoFilter,
+                            FilterType::Sub,
+                            FilterType::Up,
+                            FilterType::Avg,
+                            FilterType::Paeth,
+                        ] {
+                            changed |= ui
+                                .selectable_value(
+                                    &mut self.png_filter,
+                                    filter,
+                                    format!("{filter:?}"),
+                                )
+                                .changed();
+                        }
+                    });
+            }
+            Jpeg => {
+                changed |= ui
+                    .add(Slider::new(&mut self.jpeg_quality, 1..=100).text("quality"))
+                    .changed();
+                ui.horizontal(|ui| {
+                    ui.label("Colors");
+                    for (sampling, name, hover) in [
+                        (
+                            SamplingFactor::R_4_4_4,
+                            "4:4:4",
+                            "Colors at full resolution",
+                        ),
+                        (SamplingFactor::R_4_2_2, "4:2:2", "Colors at half the width"),
+                        (
+                            SamplingFactor::R_4_2_0,
+                            "4:2:0",
+                            "Colors at half the width and height",
+                        ),
+                    ] {
+                        changed |= ui
+                            .radio_value(&mut self.jpeg_sampling, sampling, name)
+                            .on_hover_text(hover)
+                            .changed();
+                    }
+                });
+                ui.label("JPEG has no transparency.");
+            }
+            WebP => {
+                ui.horizontal(|ui| {
+                    if ui
+                        .radio(
+                            !self.webp_lossy && self.webp_near_lossless == 100,
+                            "Lossless",
+                        )
+                        .clicked()
+                    {
+                        self.webp_lossy = false;
+                        self.webp_near_lossless = 100;
+                        changed = true;
+                    }
+                    if ui
+                        .radio(
+                            !self.webp_lossy && self.webp_near_lossless < 100,
+                            "Near lossless",
+                        )
+                        .clicked()
+                    {
+                        self.webp_lossy = false;
+                        self.webp_near_lossless = self.webp_near_lossless.min(60);
+                        changed = true;
+                    }
+                    changed |= ui
+                        .radio_value(&mut self.webp_lossy, true, "Lossy")
+                        .changed();
+                });
+                if self.webp_lossy {
+                    changed |= ui
+                        .add(Slider::new(&mut self.webp_quality, 0..=100).text("quality"))
+                        .changed();
+                } else if self.webp_near_lossless < 100 {
+                    changed |= ui
+                        .add(Slider::new(&mut self.webp_near_lossless, 0..=99).text("level"))
+                        .on_hover_text("Lower levels change colors more, which compresses better")
+                        .changed();
+                }
+            }
+            Qoi | Tga | Bmp => {
+                ui.label("This format has no options.");
+            }
+        }
+        changed
+    }
+}
+
+/// Encodes the image into the bytes of a file
+pub fn encode(
+    image: &Array<Color32, 2>,
+    format: ExportFormat,
+    options: &ExportOptions,
+) -> Result<Vec<u8>, String> {
+    let [w, h] = image.size();
+    let (w, h) = (w as u32, h as u32);
+    let rgba: Vec<u8> = image
+        .as_flattened()
+        .iter()
+        .flat_map(|c| c.to_srgba_unmultiplied())
+        .collect();
+    let mut file = Vec::new();
+    let result = match format {
+        Png => PngEncoder::new_with_quality(&mut file, options.png_compression, options.png_filter)
+            .write_image(&rgba, w, h, ColorType::Rgba8),
+        Jpeg => {
+            let (Ok(w), Ok(h)) = (u16::try_from(w), u16::try_from(h)) else {
+                return Err("JPEG images can be at most 65535 pixels wide and high".to_owned());
+            };
+            let rgb: Vec<u8> = rgba
+                .chunks_exact(4)
+                .flat_map(|px| [px[0], px[1], px[2]])
+                .collect();
+            let mut encoder = jpeg_encoder::Encoder::new(&mut file, options.jpeg_quality);
+            encoder.set_sampling_factor(options.jpeg_sampling);
+            encoder
+                .encode(&rgb, w, h, jpeg_encoder::ColorType::Rgb)
+                .map_err(|e| e.to_string())?;
+            return Ok(file);
+        }
+        WebP => return encode_webp(&rgba, w, h, options),
+        Qoi => QoiEncoder::new(&mut file).write_image(&rgba, w, h, ColorType::Rgba8),
+        Tga => TgaEncoder::new(&mut file).write_image(&rgba, w, h, ColorType::Rgba8),
+        Bmp => BmpEncoder::new(&mut file).write_image(&rgba, w, h, ColorType::Rgba8),
+    };
+    result.map_err(|e| e.to_string())?;
+    Ok(file)
+}
+
+/// The largest width and height libwebp can encode
+const WEBP_MAX_SIZE: u32 = 16383;
+
+fn encode_webp(rgba: &[u8], w: u32, h: u32, options: &ExportOptions) -> Result<Vec<u8>, String> {
+    if w > WEBP_MAX_SIZE || h > WEBP_MAX_SIZE {
+        return Err(format!(
+            "WebP images can be at most {WEBP_MAX_SIZE} pixels wide and high"
+        ));
+    }
+    let mut config =
+        WebPConfig::new().map_err(|_| "unable to set up the WebP encoder".to_owned())?;
+    if options.webp_lossy {
+        config.lossless = 0;
+        config.quality = options.webp_quality as f32;
+    } else {
+        config.lossless = 1;
+        config.near_lossless = options.webp_near_lossless as i32;
+    }
+    let file = webp::Encoder::from_rgba(rgba, w, h)
+        .encode_advanced(&config)
+        .map_err(|e| format!("unable to encode WebP: {e:?}"))?;
+    Ok(file.to_vec())
+}
+
+fn format_size(bytes: usize) -> String {
+    match bytes {
+        0..=1023 => format!("{bytes} bytes"),
+        1024..=1048575 => format!("{:.1} KiB", bytes as f32 / 1024.0),
+        _ => format!("{:.2} MiB", bytes as f32 / 1048576.0),
+    }
+}
+
+/// Picks the format and its options, showing how big the file will be before writing it
+pub struct ExportDialog {
+    path: PathBuf,
+    format: ExportFormat,
+    options: ExportOptions,
+    /// the file with the current options, or why it can't be written
+    file: Result<Vec<u8>, String>,
+    /// whether the options changed since the file was encoded
+    outdated: bool,
+}
+
+impl ExportDialog {
+    pub fn new(app: &App, path: PathBuf) -> Self {
+        let format = path
+            .extension()
+            .and_then(|ext| ExportFormat::from_extension(&ext.to_string_lossy()))
+            .unwrap_or(Png);
+        let mut this = Self {
+            path,
+            format,
+            options: app.export_options,
+            file: Ok(Vec::new()),
+            outdated: true,
+        };
+        this.update(app);
+        this
+    }
+
+    fn update(&mut self, app: &App) {
+        self.file = encode(&app.image, self.format, &self.options);
+        self.outdated = false;
+    }
+
+    pub fn show(&mut self, app: &mut App, ctx: &Context) -> bool {
+        let mut open = true;
+        let mut done = false;
+        Window::new("Export")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let mut changed = false;
+                ui.horizontal_wrapped(|ui| {
+                    for format in ExportFormat::ALL {
+                        if ui
+                            .radio_value(&mut self.format, format, format.name())
+                            .changed()
+                        {
+                            self.path.set_extension(format.extension());
+                            changed = true;
+                        }
+                    }
+                });
+                ui.label(self.path.display().to_string());
+                ui.separator();
+                self.outdated |= changed | self.options.menu(self.format, ui);
+                // encoding can be slow, so not while a slider is dragged
+                if self.outdated && !ui.input(|i| i.pointer.any_down()) {
+                    self.update(app);
+                }
+                ui.separator();
+                match self.file {
+                    _ if self.outdated => ui.label("File size: ..."),
+                    Ok(ref file) => ui.label(format!("File size: {}", format_size(file.len()))),
+                    Err(ref e) => ui.colored_label(Color32::RED, e),
+                };
+                ui.horizontal(|ui| {
+                    let ready = !self.outdated && self.file.is_ok();
+                    if ui.add_enabled(ready, Button::new("Export")).clicked() {
+                        app.export_options = self.options;
+                        if let Ok(ref file) = self.file {
+                            if let Err(e) = fs::write(&self.path, file) {
+                                println!("Unable to export this image: {e}");
+                            }
+                        }
+                        done = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        done = true;
+                    }
+                });
+            });
+        open && !done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gradients with a half transparent square on them
+    fn image() -> Array<Color32, 2> {
+        Array::new_by_enumeration([48, 32], |i| {
+            let (x, y) = ((i % 48) as u8, (i / 48) as u8);
+            if (10..30).contains(&x) && (6..26).contains(&y) {
+                Color32::from_rgba_unmultiplied(230, 200, 40, 128)
+            } else {
+                Color32::from_rgb(x * 5, y * 7, 255 - x - y)
+            }
+        })
+    }
+
+    /// The largest difference of any channel after decoding the file with the `image` crate
+    fn difference(format: ExportFormat, options: &ExportOptions) -> u8 {
+        let image = image();
+        let file = encode(&image, format, options).unwrap();
+        let image_format = match format {
+            Png => ::image::ImageFormat::Png,
+            Jpeg => ::image::ImageFormat::Jpeg,
+            WebP => ::image::ImageFormat::WebP,
+            Qoi => ::image::ImageFormat::Qoi,
+            Tga => ::image::ImageFormat::Tga,
+            Bmp => ::image::ImageFormat::Bmp,
+        };
+        let decoded = ::image::load_from_memory_with_format(&file, image_format)
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(decoded.dimensions(), (48, 32));
+        let channels = if format == Jpeg { 3 } else { 4 };
+        decoded
+            .pixels()
+            .zip(image.as_flattened())
+            .flat_map(|(a, b)| {
+                let b = b.to_srgba_unmultiplied();
+                (0..channels).map(move |c| a[c].abs_diff(b[c]))
+            })
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn lossless_formats_are_exact() {
+        let options = ExportOptions::default();
+        for format in [Png, WebP, Qoi, Tga, Bmp] {
+            assert_eq!(difference(format, &options), 0, "{}", format.name());
+        }
+    }
+
+    #[test]
+    fn lossy_webp_is_close() {
+        let options = ExportOptions {
+            webp_lossy: true,
+            webp_quality: 90,
+            ..Default::default()
+        };
+        assert!(difference(WebP, &options) < 40);
+        let options = ExportOptions {
+            webp_near_lossless: 40,
+            ..Default::default()
+        };
+        assert!(difference(WebP, &options) < 16);
+    }
+
+    #[test]
+    fn jpeg_sampling() {
+        let encoded = |jpeg_sampling| {
+            let options = ExportOptions {
+                jpeg_sampling,
+                ..Default::default()
+            };
+            let size = encode(&image(), Jpeg, &options).unwrap().len();
+            (difference(Jpeg, &options), size)
+        };
+        let full = encoded(SamplingFactor::R_4_4_4);
+        let half = encoded(SamplingFactor::R_4_2_2);
+        let quarter = encoded(SamplingFactor::R_4_2_0);
+        assert!(full.0 < 40);
+        // less chroma blurs the edges of the square but makes smaller files
+        assert!(full.0 <= half.0 && half.0 <= quarter.0);
+        assert!(quarter.1 < full.1);
+    }
+}
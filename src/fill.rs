@@ -1,34 +1,103 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, path::Path};
 
-use egui::Color32;
+use egui::*;
+use image::io::Reader as ImageReader;
+use micro_ndarray::Array;
 
 use crate::{
+    dialog::DialogAction,
     draw::{DrawParams, Location},
     App,
 };
 
-/// The state struct for the fill algorithm
-struct Filler<'app> {
-    col: Color32,
+/// A tile that flood fill and filled shapes repeat instead of painting a single color
+pub struct PatternFill {
+    pub enabled: bool,
+    pub tile: Option<Array<Color32, 2>>,
+    /// where the top left corner of a tile is, in image pixels
+    pub offset: [isize; 2],
+    /// how many image pixels one tile pixel covers
+    pub scale: f32,
+}
+
+impl Default for PatternFill {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            tile: None,
+            offset: [0, 0],
+            scale: 1.0,
+        }
+    }
+}
+
+impl PatternFill {
+    pub fn menu(app: &mut App, ui: &mut Ui) {
+        let has_tile = app.pattern_fill.tile.is_some();
+        ui.add_enabled(
+            has_tile,
+            Checkbox::new(&mut app.pattern_fill.enabled, "Fill with the pattern"),
+        )
+        .on_disabled_hover_text("Load a tile or use the selection first");
+        if ui.button("Load tile...").clicked() {
+            app.file_dialog(false, DialogAction::LoadFillPattern);
+            ui.close_menu();
+        }
+        if ui
+            .add_enabled(
+                app.selection.is_some(),
+                Button::new("Use selection as tile"),
+            )
+            .clicked()
+        {
+            app.fill_pattern_from_selection();
+            ui.close_menu();
+        }
+        ui.separator();
+        let fill = &mut app.pattern_fill;
+        ui.horizontal(|ui| {
+            ui.label("Offset");
+            ui.add(DragValue::new(&mut fill.offset[0]).prefix("x: "));
+            ui.add(DragValue::new(&mut fill.offset[1]).prefix("y: "));
+        });
+        ui.add(
+            Slider::new(&mut fill.scale, 0.25..=16.0)
+                .logarithmic(true)
+                .text("scale"),
+        );
+    }
+
+    /// The tile color at this image pixel, or None if there is no pattern to fill with
+    pub fn color_at(&self, x: usize, y: usize) -> Option<Color32> {
+        let tile = self.tile.as_ref().filter(|_| self.enabled)?;
+        let [w, h] = tile.size();
+        let tx = ((x as isize - self.offset[0]) as f32 / self.scale).floor() as isize;
+        let ty = ((y as isize - self.offset[1]) as f32 / self.scale).floor() as isize;
+        Some(
+            tile[[
+                tx.rem_euclid(w as isize) as usize,
+                ty.rem_euclid(h as isize) as usize,
+            ]],
+        )
+    }
+}
+
+/// The state struct for the fill algorithm, which finds the connected region around a pixel
+pub struct Filler<'a, F: Fn(Color32) -> bool> {
     open: Vec<Location>,
     closed: HashSet<Location>,
-    fill_color: Color32,
-    app: &'app mut App,
+    /// whether a pixel belongs to the region
+    matches: F,
+    image: &'a Array<Color32, 2>,
 }
 
-impl<'app> Filler<'app> {
-    fn new(app: &'app mut App, draw: DrawParams) -> Self {
+impl<'a, F: Fn(Color32) -> bool> Filler<'a, F> {
+    pub fn new(image: &'a Array<Color32, 2>, start: Location, matches: F) -> Self {
         Self {
-            col: app.image[[draw.loc.x, draw.loc.y]], // color to replace
-            open: vec![draw.loc],
+            open: vec![start],
             closed: HashSet::with_capacity(128),
-            // color to fill with
-            fill_color: Color32::from_rgb(
-                (draw.px >> 16) as u8,
-                (draw.px >> 8) as u8,
-                draw.px as u8,
-            ),
-            app,
+            matches,
+            image,
         }
     }
 
@@ -39,33 +108,35 @@ impl<'app> Filler<'app> {
         }
     }
 
-    /// Fills the area.
+    /// Finds the area.
     ///
     /// "nodes" are pixels
     /// 1. add current pixel to open list
     /// 2. pop first pixel from open list
     /// 3. add it to closed list
     /// 4. check if it is valid
-    /// 5. color it
+    /// 5. add it to the region
     /// 6. add its neighbors to the open list
     /// 7. repeat from 2 on until open list is empty
-    fn fill(&mut self) {
-        let img_size = self.app.image.size();
+    pub fn region(mut self) -> Vec<Location> {
+        let img_size = self.image.size();
+        let mut region = Vec::new();
         // until all nodes are visited
         while let Some(node) = self.open.pop() {
             // mark node done
-            self.closed.insert(node);
+            if !self.closed.insert(node) {
+                continue;
+            }
             // if node is invalid, skip it
             if node.x >= img_size[0] || node.y >= img_size[1] {
                 continue;
             }
             // if node is wrong color, skip it
-            if self.app.image[[node.x, node.y]] != self.col {
+            if !(self.matches)(self.image[[node.x, node.y]]) {
                 continue;
             }
 
-            // set color without bounds check (already done above)
-            self.app.set_px_unchecked(node.x, node.y, self.fill_color);
+            region.push(node);
 
             // mark neighbors to be visited
             self.push(node.offset(-1, 0));
@@ -73,11 +144,70 @@ impl<'app> Filler<'app> {
             self.push(node.offset(0, -1));
             self.push(node.offset(0, 1));
         }
+        region
     }
 }
 
 impl App {
     pub fn fill(&mut self, draw: DrawParams) {
-        Filler::new(self, draw).fill();
+        // filling works on regions, so symmetry is applied to the starting point instead of each pixel
+        let img_size = self.image.size();
+        let points = self
+            .effects
+            .symmetry
+            .points(draw.loc.x, draw.loc.y, img_size);
+        for loc in points {
+            if loc.x < img_size[0] && loc.y < img_size[1] {
+                // color to replace
+                let col = self.image[[loc.x, loc.y]];
+                let region = Filler::new(&self.image, loc, |c| c == col).region();
+                for Location { x, y } in region {
+                    // set color without bounds check (already done by the filler)
+                    let fill_color = self.fill_color_at(x, y, draw.px);
+                    self.set_px_unchecked(x, y, fill_color);
+                }
+            }
+        }
+    }
+
+    /// The color filling paints at this pixel, from the pattern if one is enabled and `px` otherwise
+    pub fn fill_color_at(&self, x: usize, y: usize, px: u32) -> Color32 {
+        self.pattern_fill
+            .color_at(x, y)
+            .unwrap_or_else(|| Color32::from_rgb((px >> 16) as u8, (px >> 8) as u8, px as u8))
+    }
+
+    pub fn load_fill_pattern(&mut self, path: &Path) {
+        let Some(tile) = ImageReader::open(path)
+            .ok()
+            .and_then(|reader| reader.decode().ok())
+        else {
+            println!("Unable to load this pattern.");
+            return;
+        };
+        let tile = tile.to_rgba8();
+        let (w, h) = tile.dimensions();
+        self.pattern_fill.tile = Array::from_flat(
+            tile.pixels()
+                .map(|p| Color32::from_rgba_unmultiplied(p[0], p[1], p[2], p[3]))
+                .collect(),
+            [w as usize, h as usize],
+        )
+        .filter(|_| w > 0 && h > 0);
+        self.pattern_fill.enabled = self.pattern_fill.tile.is_some();
+    }
+
+    /// Uses the selected part of the image as the tile, unselected pixels become transparent
+    pub fn fill_pattern_from_selection(&mut self) {
+        let Some(ref selection) = self.selection else {
+            return;
+        };
+        let (min, size) = selection.bounds();
+        self.pattern_fill.tile = Some(Array::new_by_enumeration(size, |i| {
+            let (x, y) = (min[0] + i % size[0], min[1] + i / size[0]);
+            let m = selection.mask[[x, y]];
+            self.image[[x, y]].gamma_multiply(m as f32 / 255.0)
+        }));
+        self.pattern_fill.enabled = true;
     }
 }
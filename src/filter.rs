@@ -0,0 +1,275 @@
+//! Filters that look at the neighborhood of each pixel: blurring, sharpening, edges
+
+use egui::*;
+use micro_ndarray::Array;
+
+use crate::{
+    popup::{Popup, PreviewDialog, Previewed},
+    App,
+};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Filter {
+    GaussianBlur {
+        sigma: f32,
+    },
+    BoxBlur {
+        radius: usize,
+    },
+    UnsharpMask {
+        sigma: f32,
+        amount: f32,
+        threshold: f32,
+    },
+    Sharpen {
+        strength: f32,
+    },
+    Emboss {
+        strength: f32,
+    },
+    Sobel {
+        scale: f32,
+    },
+}
+
+use Filter::*;
+
+impl Filter {
+    pub fn name(self) -> &'static str {
+        match self {
+            GaussianBlur { .. } => "Gaussian Blur",
+            BoxBlur { .. } => "Box Blur",
+            UnsharpMask { .. } => "Unsharp Mask",
+            Sharpen { .. } => "Sharpen",
+            Emboss { .. } => "Emboss",
+            Sobel { .. } => "Edge Detection (Sobel)",
+        }
+    }
+
+    pub fn apply(self, image: &Array<Color32, 2>) -> Array<Color32, 2> {
+        let size = image.size();
+        let px = to_f32(image);
+        let result = match self {
+            GaussianBlur { sigma } => gaussian_blur(&px, size, sigma),
+            BoxBlur { radius } => box_blur(&px, size, radius),
+            UnsharpMask {
+                sigma,
+                amount,
+                threshold,
+            } => {
+                let blurred = gaussian_blur(&px, size, sigma);
+                px.iter()
+                    .zip(blurred)
+                    .map(|(&orig, blur)| {
+                        let mut out = orig;
+                        // only color channels, alpha stays
+                        for c in 0..3 {
+                            let diff = orig[c] - blur[c];
+                            if diff.abs() >= threshold {
+                                out[c] = orig[c] + diff * amount;
+                            }
+                        }
+                        out
+                    })
+                    .collect()
+            }
+            Sharpen { strength } => {
+                let s = strength;
+                convolve_3x3(
+                    &px,
+                    size,
+                    [[0.0, -s, 0.0], [-s, 1.0 + 4.0 * s, -s], [0.0, -s, 0.0]],
+                )
+            }
+            Emboss { strength } => {
+                let s = strength;
+                convolve_3x3(
+                    &px,
+                    size,
+                    [[-2.0 * s, -s, 0.0], [-s, 1.0, s], [0.0, s, 2.0 * s]],
+                )
+            }
+            Sobel { scale } => sobel(&px, size, scale),
+        };
+        from_f32(result, size)
+    }
+}
+
+pub type Px = [f32; 4];
+
+fn to_f32(image: &Array<Color32, 2>) -> Vec<Px> {
+    image
+        .as_flattened()
+        .iter()
+        .map(|px| px.to_array().map(|c| c as f32))
+        .collect()
+}
+
+fn from_f32(px: Vec<Px>, size: [usize; 2]) -> Array<Color32, 2> {
+    let px = px
+        .into_iter()
+        .map(|[r, g, b, a]| {
+            // colors are premultiplied, so they can't be brighter than the alpha
+            let a = a.round().clamp(0.0, 255.0);
+            let c = |c: f32| c.round().clamp(0.0, a) as u8;
+            Color32::from_rgba_premultiplied(c(r), c(g), c(b), a as u8)
+        })
+        .collect();
+    Array::from_flat(px, size).unwrap()
+}
+
+/// Blurs along one axis with a (normalized) kernel centered at kernel.len() / 2. Edges are clamped.
+fn blur_1d(px: &[Px], size: [usize; 2], kernel: &[f32], horizontal: bool) -> Vec<Px> {
+    let r = (kernel.len() / 2) as isize;
+    let [w, h] = size;
+    let mut out = vec![[0.0; 4]; px.len()];
+    for y in 0..h {
+        for x in 0..w {
+            let mut sum = [0.0; 4];
+            for (k, &weight) in kernel.iter().enumerate() {
+                let d = k as isize - r;
+                let (sx, sy) = if horizontal {
+                    ((x as isize + d).clamp(0, w as isize - 1) as usize, y)
+                } else {
+                    (x, (y as isize + d).clamp(0, h as isize - 1) as usize)
+                };
+                let p = px[sx + sy * w];
+                for c in 0..4 {
+                    sum[c] += p[c] * weight;
+                }
+            }
+            out[x + y * w] = sum;
+        }
+    }
+    out
+}
+
+pub fn gaussian_blur(px: &[Px], size: [usize; 2], sigma: f32) -> Vec<Px> {
+    // 3 sigma contains practically the whole curve
+    let r = (sigma * 3.0).ceil() as isize;
+    let mut kernel: Vec<f32> = (-r..=r)
+        .map(|d| (-(d * d) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f32 = kernel.iter().sum();
+    kernel.iter_mut().for_each(|k| *k /= total);
+    let px = blur_1d(px, size, &kernel, true);
+    blur_1d(&px, size, &kernel, false)
+}
+
+fn box_blur(px: &[Px], size: [usize; 2], radius: usize) -> Vec<Px> {
+    let kernel = vec![1.0 / (radius * 2 + 1) as f32; radius * 2 + 1];
+    let px = blur_1d(px, size, &kernel, true);
+    blur_1d(&px, size, &kernel, false)
+}
+
+/// Applies a 3x3 kernel (indexed [y][x]) to the color channels. Edges are clamped.
+fn convolve_3x3(px: &[Px], size: [usize; 2], kernel: [[f32; 3]; 3]) -> Vec<Px> {
+    let [w, h] = size;
+    let mut out = px.to_vec();
+    for y in 0..h {
+        for x in 0..w {
+            let mut sum = [0.0; 3];
+            for (ky, row) in kernel.iter().enumerate() {
+                for (kx, &weight) in row.iter().enumerate() {
+                    let sx = (x + kx).saturating_sub(1).min(w - 1);
+                    let sy = (y + ky).saturating_sub(1).min(h - 1);
+                    let p = px[sx + sy * w];
+                    for c in 0..3 {
+                        sum[c] += p[c] * weight;
+                    }
+                }
+            }
+            out[x + y * w][..3].copy_from_slice(&sum);
+        }
+    }
+    out
+}
+
+/// Edge strength as a grayscale image
+fn sobel(px: &[Px], size: [usize; 2], scale: f32) -> Vec<Px> {
+    let gray: Vec<Px> = px
+        .iter()
+        .map(|&[r, g, b, a]| {
+            let l = 0.299 * r + 0.587 * g + 0.114 * b;
+            [l, l, l, a]
+        })
+        .collect();
+    let gx = convolve_3x3(
+        &gray,
+        size,
+        [[-1.0, 0.0, 1.0], [-2.0, 0.0, 2.0], [-1.0, 0.0, 1.0]],
+    );
+    let gy = convolve_3x3(
+        &gray,
+        size,
+        [[-1.0, -2.0, -1.0], [0.0, 0.0, 0.0], [1.0, 2.0, 1.0]],
+    );
+    gx.iter()
+        .zip(gy)
+        .map(|(x, y)| {
+            let l = (x[0] * x[0] + y[0] * y[0]).sqrt() * scale;
+            [l, l, l, x[3]]
+        })
+        .collect()
+}
+
+pub type FilterDialog = PreviewDialog<Filter>;
+
+impl Previewed for Filter {
+    fn title(&self) -> &'static str {
+        self.name()
+    }
+
+    fn params(&mut self, ui: &mut Ui) -> bool {
+        match self {
+            GaussianBlur { sigma } => ui
+                .add(Slider::new(sigma, 0.1..=50.0).text("radius (sigma)"))
+                .changed(),
+            BoxBlur { radius } => ui.add(Slider::new(radius, 1..=50).text("radius")).changed(),
+            UnsharpMask {
+                sigma,
+                amount,
+                threshold,
+            } => {
+                ui.add(Slider::new(sigma, 0.1..=20.0).text("radius (sigma)"))
+                    .changed()
+                    | ui.add(Slider::new(amount, 0.0..=5.0).text("amount"))
+                        .changed()
+                    | ui.add(Slider::new(threshold, 0.0..=255.0).text("threshold"))
+                        .changed()
+            }
+            Sharpen { strength } | Emboss { strength } => ui
+                .add(Slider::new(strength, 0.0..=5.0).text("strength"))
+                .changed(),
+            Sobel { scale } => ui
+                .add(Slider::new(scale, 0.1..=5.0).text("scale"))
+                .changed(),
+        }
+    }
+
+    fn result(&mut self, original: &Array<Color32, 2>) -> Array<Color32, 2> {
+        self.apply(original)
+    }
+}
+
+impl App {
+    pub fn filter_menu(&mut self, ui: &mut Ui) {
+        for filter in [
+            GaussianBlur { sigma: 2.0 },
+            BoxBlur { radius: 2 },
+            UnsharpMask {
+                sigma: 2.0,
+                amount: 1.0,
+                threshold: 0.0,
+            },
+            Sharpen { strength: 0.5 },
+            Emboss { strength: 1.0 },
+            Sobel { scale: 1.0 },
+        ] {
+            if ui.button(format!("{}...", filter.name())).clicked() {
+                self.popup = Some(Popup::Filter(FilterDialog::new(self, filter)));
+                ui.close_menu();
+            }
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! A grid drawn over the canvas (never into the image) that points can snap to
+
+use egui::*;
+
+use crate::{draw::Location, App};
+
+pub struct Grid {
+    pub show: bool,
+    pub snap: bool,
+    pub cell: usize,
+    pub offset: [usize; 2],
+    pub color: Color32,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Self {
+            show: false,
+            snap: false,
+            cell: 16,
+            offset: [0, 0],
+            color: Color32::from_rgba_unmultiplied(128, 128, 128, 128),
+        }
+    }
+}
+
+impl Grid {
+    pub fn menu(app: &mut App, ui: &mut Ui) {
+        let grid = &mut app.grid;
+        ui.checkbox(&mut grid.show, "Show grid");
+        ui.checkbox(&mut grid.snap, "Snap to grid");
+        ui.horizontal(|ui| {
+            ui.label("Cell size");
+            ui.add(DragValue::new(&mut grid.cell).clamp_range(1..=1024));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Offset");
+            ui.add(DragValue::new(&mut grid.offset[0]).prefix("x: "));
+            ui.add(DragValue::new(&mut grid.offset[1]).prefix("y: "));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Color");
+            ui.color_edit_button_srgba(&mut grid.color);
+        });
+    }
+
+    /// Moves a location to the closest grid intersection if snapping is on
+    pub fn snap(&self, loc: Location) -> Location {
+        if !self.snap {
+            return loc;
+        }
+        let snap = |v: usize, offset: usize| {
+            let cell = self.cell.max(1) as f32;
+            let offset = (offset % self.cell.max(1)) as f32;
+            (((v as f32 - offset) / cell).round() * cell + offset) as usize
+        };
+        Location::new(snap(loc.x, self.offset[0]), snap(loc.y, self.offset[1]))
+    }
+
+    /// Draws the grid lines on top of the canvas
+    pub fn render(&self, app: &App, painter: &Painter, canvas: Rect) {
+        if !self.show {
+            return;
+        }
+        let cell = self.cell.max(1);
+        // don't bother drawing a grid that would just fill the canvas with lines
+        if app.to_point(cell) < 3.0 {
+            return;
+        }
+        let stroke = Stroke::new(1.0, self.color);
+        let size = app.image.size();
+        // lines are placed on the pixel edges, +0.5 makes them hit a single row of screen pixels
+        for x in (self.offset[0] % cell..=size[0]).step_by(cell) {
+            let x = canvas.min.x + app.to_point(x) + 0.5;
+            painter.vline(x, canvas.y_range(), stroke);
+        }
+        for y in (self.offset[1] % cell..=size[1]).step_by(cell) {
+            let y = canvas.min.y + app.to_point(y) + 0.5;
+            painter.hline(canvas.x_range(), y, stroke);
+        }
+    }
+}
@@ -4,6 +4,6 @@ use crate::App;
 
 impl App {
     pub(crate) fn render_help(&mut self, ui: &mut Ui) {
-        ui.add_sized(vec2(300.0, 30.0), Label::new(RichText::new("You can select tools and colors in the window menu. \nTo draw the shapes with arbitrary sizes, use the right mouse button and hold shift to draw precise squares / equilateral triangles / circles.")));
+        ui.add_sized(vec2(300.0, 30.0), Label::new(RichText::new("You can select tools and colors in the window menu. \nTo draw the shapes with arbitrary sizes, use the right mouse button and hold shift to draw precise squares / equilateral triangles / circles. \nWith the paintbrush and fill, the right mouse button uses the secondary color instead, press X to swap the two colors. \nThe Eraser tool has its own size, shape and softness under Tools > Eraser options, the Airbrush keeps spraying while the button is held. \nThe Clone Stamp and Healing Brush copy from a source set with Alt + click. \nSmudge, Blur, Sharpen, Dodge and Burn change the pixels under the brush, see Tools > Retouch options. \nFill and filled shapes can repeat a tile instead of one color, see Tools > Fill pattern. \nEffects > Pattern limits which pixels the current tool paints, like a checkerboard, dithering or lines. \nWith symmetry enabled (Effects menu), hold C to move the symmetry center to the mouse. \nThe Grid menu can show a grid and snap pulled shapes, lines and selections to it. \nThe Select tool limits painting to a rectangle, clicking without dragging deselects. The Magic Wand selects similar colors, hold Shift to add, Alt to subtract or both to intersect. \nFile > Export... has options for each format and shows the file size before writing it. \nFrames > Show timeline adds frames for animations, with onion skinning and playback, and exports them as GIF, APNG or PNG sequences. \nImages can be opened from and saved to OpenRaster (.ora) files, their layers are merged when opening. \nSaving with a .paint extension keeps the selection, palette and tool settings along with the image. \nClick a swatch in the palette panel to use its color, right click it to rename, move or remove it.")));
     }
 }
@@ -1,48 +1,168 @@
-use egui::{Color32, Pos2, Rect};
-use image::{io::Reader as ImageReader, DynamicImage, ImageBuffer, Rgb};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Cursor},
+    path::Path,
+};
+
+use egui::Color32;
+use image::{
+    io::Reader as ImageReader, DynamicImage, ImageBuffer, ImageOutputFormat, Rgba, RgbaImage,
+};
 use micro_ndarray::Array;
 
-use crate::App;
+use crate::{
+    export::{encode, ExportFormat},
+    ora::is_ora,
+    project::is_project,
+    quantize::{remap, Dither},
+    App,
+};
 
 impl App {
     /// SAFETY: Call only when self.filename is present
     /// loads a file from disk (called after open dialog is confirmed)
     pub fn load(&mut self) {
-        if let Ok(x) = ImageReader::open(self.filename.as_ref().unwrap())
-            .expect("This file can't be opened due to an IO error")
-            .decode()
-        {
-            self.image = Array::from_flat(
-                x.to_rgb8()
-                    .pixels()
-                    .map(|&Rgb([r, g, b])| Color32::from_rgb(r, g, b))
-                    .collect::<Vec<_>>(),
-                [x.width() as usize, x.height() as usize],
-            )
-            .unwrap();
-            self.changes.all(Rect::from_min_max(
-                Pos2::ZERO,
-                Pos2::new(x.width() as f32, x.height() as f32),
-            ));
-        } else {
-            self.filename = None;
-            println!("Unable to load this image.");
+        let path = Path::new(self.filename.as_ref().unwrap()).to_owned();
+        if is_project(&path) || is_ora(&path) {
+            let loaded = if is_project(&path) {
+                self.load_project(&path)
+            } else {
+                self.load_ora(&path)
+            };
+            if let Err(e) = loaded {
+                self.filename = None;
+                println!("Unable to load this file: {e}");
+            }
+            return;
+        }
+        let decoded = ImageReader::open(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|reader| reader.decode().map_err(|e| e.to_string()));
+        match decoded {
+            Ok(x) => {
+                // the canvas takes the size of the file instead of cropping it to the window
+                self.replace_image(from_rgba_image(&x.to_rgba8()));
+                self.reset_animation();
+            }
+            Err(e) => {
+                self.filename = None;
+                println!("Unable to load this image: {e}");
+            }
         }
     }
 
     /// SAFETY: Call only when self.filename is present
     /// saves the image to disk
     pub fn save(&mut self) {
+        let path = Path::new(self.filename.as_ref().unwrap()).to_owned();
+        if is_project(&path) {
+            if let Err(e) = self.save_project(&path) {
+                println!("Unable to save this project: {e}");
+            }
+            return;
+        }
+        if is_ora(&path) {
+            if let Err(e) = self.save_ora(&path) {
+                println!("Unable to save this image: {e}");
+            }
+            return;
+        }
+        // formats with options are saved with the ones from the last export
+        let Some(format) = path
+            .extension()
+            .and_then(|ext| ExportFormat::from_extension(&ext.to_string_lossy()))
+        else {
+            println!("Unable to save this image: unsupported file type");
+            return;
+        };
+        let written = encode(&self.image, format, &self.export_options)
+            .and_then(|file| fs::write(&path, file).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            println!("Unable to save this image: {e}");
+        }
+    }
+
+    /// saves the image with a palette, as GIF if the path ends in .gif and as PNG otherwise.
+    /// Pixels that are mostly transparent get their own fully transparent palette entry.
+    pub fn save_indexed(&mut self, path: &Path) -> Result<(), String> {
+        let transparent = self
+            .image
+            .as_flattened()
+            .iter()
+            .any(|px| px.to_srgba_unmultiplied()[3] < 128);
+        let palette = self.palette_for_indexed(if transparent { 255 } else { 256 });
+        let (mut indices, _) = remap(&self.image, &palette, Dither::None);
+        let mut flat_palette = palette.concat();
+        let transparent_index = transparent.then_some(palette.len() as u8);
+        if let Some(index) = transparent_index {
+            for (i, px) in indices.iter_mut().zip(self.image.as_flattened()) {
+                if px.to_srgba_unmultiplied()[3] < 128 {
+                    *i = index;
+                }
+            }
+            flat_palette.extend([0, 0, 0]);
+        }
         let size = self.image.size();
-        DynamicImage::ImageRgb8(ImageBuffer::from_fn(
-            size[0] as u32,
-            size[1] as u32,
-            |x, y| {
-                let px: Color32 = self.image[[x as usize, y as usize]];
-                Rgb(px.to_array()[0..3].try_into().unwrap())
-            },
-        ))
-        .save(self.filename.as_ref().unwrap())
-        .expect("This file can't be saved to due to an IO error");
+        let file = BufWriter::new(File::create(path).map_err(|e| e.to_string())?);
+        let is_gif = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("gif"));
+        if is_gif {
+            let mut encoder =
+                gif::Encoder::new(file, size[0] as u16, size[1] as u16, &flat_palette)
+                    .map_err(|e| e.to_string())?;
+            let frame = gif::Frame {
+                width: size[0] as u16,
+                height: size[1] as u16,
+                buffer: indices.into(),
+                transparent: transparent_index,
+                ..Default::default()
+            };
+            encoder.write_frame(&frame).map_err(|e| e.to_string())
+        } else {
+            let mut encoder = png::Encoder::new(file, size[0] as u32, size[1] as u32);
+            encoder.set_color(png::ColorType::Indexed);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_palette(flat_palette);
+            if let Some(index) = transparent_index {
+                // the alpha of each palette entry, only the last one is transparent
+                let mut trns = vec![255; index as usize];
+                trns.push(0);
+                encoder.set_trns(trns);
+            }
+            encoder
+                .write_header()
+                .and_then(|mut writer| writer.write_image_data(&indices))
+                .map_err(|e| e.to_string())
+        }
     }
 }
+
+/// encodes an image as PNG in memory, for the files that are containers of images
+pub fn encode_png(image: DynamicImage) -> Vec<u8> {
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+        .expect("encoding a PNG in memory can't fail");
+    png
+}
+
+/// converts the canvas into an [`RgbaImage`] so the `image` crate's operations can be used on it
+pub fn to_rgba_image(image: &Array<Color32, 2>) -> RgbaImage {
+    let size = image.size();
+    ImageBuffer::from_fn(size[0] as u32, size[1] as u32, |x, y| {
+        Rgba(image[[x as usize, y as usize]].to_srgba_unmultiplied())
+    })
+}
+
+/// converts an [`RgbaImage`] back into a canvas
+pub fn from_rgba_image(image: &RgbaImage) -> Array<Color32, 2> {
+    Array::from_flat(
+        image
+            .pixels()
+            .map(|&Rgba([r, g, b, a])| Color32::from_rgba_unmultiplied(r, g, b, a))
+            .collect::<Vec<_>>(),
+        [image.width() as usize, image.height() as usize],
+    )
+    .unwrap()
+}
@@ -1,6 +1,10 @@
 use std::{process, sync::Arc, time::Duration};
 
-use color::{ColorConvert, DrawColor};
+use airbrush::Airbrush;
+use animation::Animation;
+use blend::BlendMode;
+use clone::CloneStamp;
+use color::ColorConvert;
 use compress::ChangeRect;
 use draw::{DrawParams, Location};
 use eframe::CreationContext;
@@ -10,20 +14,56 @@ use egui::*;
 use dialog::DialogAction;
 use effects::Effects;
 use egui_file::FileDialog;
+use eraser::Eraser;
+use export::ExportOptions;
+use fill::PatternFill;
+use grid::Grid;
 use micro_ndarray::Array;
 use mode::Mode;
+use palette::{GeneratePaletteDialog, Palette};
+use pattern::Pattern;
+use picker::ColorPanel;
+use popup::Popup;
+use retouch::Retouch;
+use select::{MagicWand, Selection};
+use stroke::StrokeBuffer;
+use symmetry::{Symmetry, SymmetryMode};
 
+mod adjust;
+mod airbrush;
+mod animation;
+mod blend;
+mod clone;
 mod color;
 mod compress;
 mod dialog;
 mod draw;
 mod effects;
+mod eraser;
+mod export;
 mod fill;
+mod filter;
+mod grid;
 mod help;
 mod io;
+mod mask;
 mod mode;
+mod ora;
+mod palette;
+mod pattern;
+mod picker;
+mod popup;
+mod project;
 mod pull;
+mod quantize;
+mod resize;
+mod retouch;
+mod select;
+mod stroke;
+mod symmetry;
 mod tex;
+mod transform;
+mod zip;
 
 fn main() {
     let native_options = eframe::NativeOptions::default();
@@ -40,20 +80,51 @@ pub struct App {
     pub real_image: Array<Color32, 2>,
     pub changes: ChangeRect,
     pub tex: TextureId,
+    /// None means the canvas is as big as the window
+    pub canvas_size: Option<[usize; 2]>,
 
     pub filename: Option<String>,
     pub dialog_action: Option<DialogAction>,
     pub dialog: Option<FileDialog>,
+    pub popup: Option<Popup>,
 
-    pub color: DrawColor,
+    pub palette: Palette,
+    pub show_palette: bool,
+    pub color_panel: ColorPanel,
+    /// the primary color is `draw.px`
     pub draw: DrawParams,
+    /// used by the right mouse button, for filling shapes and by the eraser
+    pub secondary_px: u32,
+    /// how painted pixels combine with the ones below
+    pub blend_mode: BlendMode,
+    /// the most a stroke can cover the pixels below it
+    pub opacity: f32,
+    /// how much each dab of a stroke covers
+    pub flow: f32,
+    pub stroke: Option<StrokeBuffer>,
+    /// the most recently used colors, newest first
+    pub recent_colors: Vec<u32>,
     pub last_mouse_pos: Option<DrawParams>,
 
+    /// the options used by the last export, and when saving to a format that has them
+    pub export_options: ExportOptions,
+    /// the palette from the last Reduce Colors, used for indexed exports
+    pub indexed_palette: Option<Vec<quantize::Rgb>>,
+
     pub mode: Mode,
     pub effects: Effects,
+    pub grid: Grid,
 
     pub pull_start: Option<[usize; 2]>,
-    pub eraser: bool,
+    pub selection: Option<Selection>,
+    pub select_start: Option<Location>,
+    pub magic_wand: MagicWand,
+    pub eraser: Eraser,
+    pub airbrush: Airbrush,
+    pub clone_stamp: CloneStamp,
+    pub retouch: Retouch,
+    pub pattern_fill: PatternFill,
+    pub animation: Animation,
 
     pub(crate) cur_edit: Option<String>,
     pub(crate) pixels_per_point: f32,
@@ -61,6 +132,7 @@ pub struct App {
 
 impl App {
     pub fn new(cc: &CreationContext) -> App {
+        let effects = Effects::default();
         Self {
             image: Array::new_with([100, 100], Color32::WHITE),
             real_image: Array::new_with([100, 100], Color32::WHITE),
@@ -75,18 +147,39 @@ impl App {
                     minification: TextureFilter::Linear,
                 },
             ),
+            canvas_size: None,
             filename: None,
             dialog_action: None,
             dialog: None,
+            popup: None,
             last_mouse_pos: None,
+            indexed_palette: None,
+            export_options: ExportOptions::default(),
             mode: Mode::Paintbrush,
-            color: DrawColor::Black,
+            palette: Palette::default(),
+            show_palette: true,
+            color_panel: ColorPanel::default(),
             draw: DrawParams::new(0, 0, 1, 0x000000),
+            secondary_px: 0xffffff,
+            blend_mode: BlendMode::Normal,
+            opacity: 1.0,
+            flow: 1.0,
+            stroke: None,
+            recent_colors: Vec::new(),
             changes: ChangeRect::new(20),
             cur_edit: None,
-            effects: Effects::default(),
+            grid: Grid::default(),
             pull_start: None,
-            eraser: false,
+            selection: None,
+            select_start: None,
+            magic_wand: MagicWand::default(),
+            eraser: Eraser::default(),
+            airbrush: Airbrush::new(effects.seed),
+            effects,
+            clone_stamp: CloneStamp::default(),
+            retouch: Retouch::default(),
+            pattern_fill: PatternFill::default(),
+            animation: Animation::default(),
             pixels_per_point: 1.0,
         }
     }
@@ -106,6 +199,7 @@ impl eframe::App for App {
         self.pixels_per_point = ctx.pixels_per_point();
 
         self.handle_dialogs(ctx);
+        self.handle_popups(ctx);
 
         // the content frame
         let f = Frame::none()
@@ -115,6 +209,8 @@ impl eframe::App for App {
         // create the menu bar
         TopBottomPanel::top("menubar").frame(f).show(ctx, |ui| {
             menu::bar(ui, |ui| {
+                // popups work on the current image, so it can't be changed while one is open
+                ui.set_enabled(self.popup.is_none());
                 ui.horizontal(|ui| {
                     ui.menu_button("File", |ui| {
                         if self.filename.is_some() && ui.button("Reload from file").clicked() {
@@ -133,20 +229,86 @@ impl eframe::App for App {
                         if ui.button("Save as...").clicked() {
                             self.save_file(true);
                         }
+                        if ui.button("Export...").clicked() {
+                            self.file_dialog(true, DialogAction::Export);
+                        }
+                        if ui.button("Export indexed (PNG/GIF)...").clicked() {
+                            self.export_indexed_file();
+                        }
                         if ui.button("Close").clicked() {
                             process::exit(0);
                         }
                     });
+                    ui.menu_button("Image", |ui| {
+                        self.resize_menu(ui);
+                        ui.separator();
+                        self.transform_menu(ui);
+                        ui.separator();
+                        self.quantize_menu(ui);
+                    });
+                    ui.menu_button("Select", |ui| {
+                        self.select_menu(ui);
+                    });
+                    ui.menu_button("Filters", |ui| {
+                        self.filter_menu(ui);
+                    });
+                    ui.menu_button("Adjustments", |ui| {
+                        self.adjust_menu(ui);
+                    });
                     ui.menu_button("Tools", |ui| {
                         Mode::menu(self, ui);
+                        ui.separator();
+                        ui.menu_button("Eraser options", |ui| Eraser::menu(self, ui));
+                        ui.menu_button("Airbrush options", |ui| Airbrush::menu(self, ui));
+                        ui.menu_button("Clone options", |ui| CloneStamp::menu(self, ui));
+                        ui.menu_button("Retouch options", |ui| Retouch::menu(self, ui));
+                        ui.menu_button("Magic wand options", |ui| MagicWand::menu(self, ui));
+                        ui.menu_button("Fill pattern", |ui| PatternFill::menu(self, ui));
                     });
                     ui.menu_button("Color", |ui| {
-                        DrawColor::menu(self, ui);
+                        ui.checkbox(&mut self.color_panel.show, "Show color panel");
+                        ui.checkbox(&mut self.show_palette, "Show palette");
+                        if ui.button("Load palette...").clicked() {
+                            self.file_dialog(false, DialogAction::LoadPalette);
+                            ui.close_menu();
+                        }
+                        if ui.button("Save palette...").clicked() {
+                            self.file_dialog(true, DialogAction::SavePalette);
+                            ui.close_menu();
+                        }
+                        if ui.button("Generate palette from image...").clicked() {
+                            self.popup =
+                                Some(Popup::GeneratePalette(GeneratePaletteDialog::new(self)));
+                            ui.close_menu();
+                        }
+                        if ui.button("Reset palette").clicked() {
+                            self.palette = Palette::default();
+                            ui.close_menu();
+                        }
                     });
                     let mut col = self.draw.px.into_colorf();
-                    if ui.color_edit_button_rgb(&mut col).changed() {
+                    if ui
+                        .color_edit_button_rgb(&mut col)
+                        .on_hover_text("Primary color")
+                        .changed()
+                    {
                         self.draw.px = col.into_color();
                     }
+                    let mut col = self.secondary_px.into_colorf();
+                    if ui
+                        .color_edit_button_rgb(&mut col)
+                        .on_hover_text("Secondary color, X swaps them")
+                        .changed()
+                    {
+                        self.secondary_px = col.into_color();
+                    }
+                    ui.menu_button("Brush", |ui| {
+                        BlendMode::menu(self, ui);
+                        ui.add(Slider::new(&mut self.flow, 0.01..=1.0).text("flow"))
+                            .on_hover_text(
+                                "How much each dab adds, overlapping dabs build up to the opacity",
+                            );
+                    });
                     ui.menu_button("Size", |ui| {
                         if self.cur_edit.is_none() {
                             self.cur_edit = Some(self.draw.size.to_string());
@@ -159,11 +321,31 @@ impl eframe::App for App {
                     });
                     ui.menu_button("Effects", |ui| {
                         ui.checkbox(&mut self.effects.randomize_size, "Randomize sizes");
-                        ui.checkbox(&mut self.effects.checkerboard, "Checkerboard");
+                        ui.menu_button("Pattern", |ui| Pattern::menu(self, ui));
+                        ui.checkbox(
+                            &mut self.effects.fill_shapes,
+                            "Fill shapes with the secondary color",
+                        );
+                        ui.horizontal(|ui| {
+                            ui.label("Random seed");
+                            ui.add(DragValue::new(&mut self.effects.seed));
+                            if ui
+                                .button("Restart")
+                                .on_hover_text("Repeats the same random results from the start")
+                                .clicked()
+                            {
+                                self.effects.reseed();
+                                self.airbrush.reseed(self.effects.seed);
+                            }
+                        });
+                        ui.separator();
+                        Symmetry::menu(self, ui);
+                    });
+                    ui.menu_button("Frames", |ui| Animation::menu(self, ui));
+                    ui.menu_button("Grid", |ui| {
+                        Grid::menu(self, ui);
                     });
                     ui.menu_button("Help", |ui| self.render_help(ui));
-                    ui.add_space(50.0);
-                    ui.checkbox(&mut self.eraser, "Eraser");
                 })
             })
         });
@@ -171,6 +353,22 @@ impl eframe::App for App {
         // updates things set in the debug menu
         self.update_effects();
 
+        if self.color_panel.show {
+            SidePanel::left("color")
+                .resizable(false)
+                .exact_width(210.0)
+                .show(ctx, |ui| ColorPanel::panel(self, ui));
+        }
+        if self.show_palette {
+            SidePanel::right("palette")
+                .default_width(150.0)
+                .show(ctx, |ui| Palette::panel(self, ui));
+        }
+
+        if self.animation.show_timeline {
+            TopBottomPanel::bottom("timeline").show(ctx, |ui| Animation::timeline(self, ui));
+        }
+
         CentralPanel::default().frame(f).show(ctx, |ui| {
             align_cursor(ui);
             // shows the image
@@ -181,86 +379,201 @@ impl eframe::App for App {
             );
             self.image_to_texture(&mut ctx.tex_manager().write());
 
-            // draw the texture
-            let r = ui.add(
-                Image::from_texture(SizedTexture::new(self.tex, size)).fit_to_original_size(1.0),
-            );
+            // the canvas may be smaller or bigger than the window if it has a fixed size
+            let img_size = self.image.size();
+            let size = vec2(self.to_point(img_size[0]), self.to_point(img_size[1]));
+            ScrollArea::both()
+                .drag_to_scroll(false)
+                .show(ui, |ui| self.show_canvas(ui, size));
+        });
+    }
+}
 
-            // handle keyboard and mouse input
-            ui.input(|inp| {
-                // get pointer pos offset to be in the image or return if its not inside the window
-                let Some(pointer_pos) = r.hover_pos().map(|pos| pos - r.rect.min) else {
-                    return;
+impl App {
+    /// draws the canvas and its overlays and handles the input on it
+    fn show_canvas(&mut self, ui: &mut Ui, size: Vec2) {
+        align_cursor(ui);
+        // a checkerboard behind the canvas shows where it is transparent
+        let canvas = Rect::from_min_size(ui.cursor().min, size);
+        let painter = ui
+            .painter()
+            .with_clip_rect(canvas.intersect(ui.clip_rect()));
+        let cell = 8.0;
+        let visible = painter.clip_rect();
+        painter.rect_filled(visible, 0.0, Color32::WHITE);
+        for y in ((visible.min.y - canvas.min.y) / cell) as usize
+            ..=((visible.max.y - canvas.min.y) / cell) as usize
+        {
+            for x in ((visible.min.x - canvas.min.x) / cell) as usize
+                ..=((visible.max.x - canvas.min.x) / cell) as usize
+            {
+                if (x + y).is_multiple_of(2) {
+                    let min = canvas.min + vec2(x as f32, y as f32) * cell;
+                    painter.rect_filled(
+                        Rect::from_min_size(min, Vec2::splat(cell)),
+                        0.0,
+                        Color32::LIGHT_GRAY,
+                    );
+                }
+            }
+        }
+        // draw the texture
+        let r = ui
+            .add(Image::from_texture(SizedTexture::new(self.tex, size)).fit_to_original_size(1.0));
+        let painter = ui.painter().with_clip_rect(r.rect);
+        Animation::render_onion(self, &painter, r.rect);
+        self.grid.render(self, &painter, r.rect);
+        self.effects.symmetry.render_axes(self, &painter, r.rect);
+        if let Some(ref selection) = self.selection {
+            selection.render(self, &painter, r.rect);
+        }
+        if matches!(self.mode, Mode::CloneStamp | Mode::Heal) {
+            let hover = r.hover_pos().map(|pos| {
+                Location::new(
+                    self.to_px(pos.x - r.rect.min.x),
+                    self.to_px(pos.y - r.rect.min.y),
+                )
+            });
+            self.clone_stamp.render(self, &painter, r.rect, hover);
+        }
+
+        // handle keyboard and mouse input
+        // can't be requested while the input is locked
+        let mut repaint = false;
+        ui.input(|inp| {
+            // the stroke ends once nothing draws anymore, wherever the pointer is
+            if !inp.pointer.any_down() && inp.keys_down.is_empty() {
+                self.end_stroke();
+            }
+
+            // get pointer pos offset to be in the image or return if its not inside the window
+            let Some(pointer_pos) = r.hover_pos().map(|pos| pos - r.rect.min) else {
+                return;
+            };
+            let pos = Location::new(self.to_px(pointer_pos.x), self.to_px(pointer_pos.y));
+            // return if not actually on the image
+            if !r.hovered() {
+                return; // we don't need to handle it if it's not in focus
+            }
+            // popups work on the current image, so it can't be changed while one is open
+            if self.popup.is_some() {
+                return;
+            }
+
+            // move the symmetry center
+            if inp.key_down(Key::C) && self.effects.symmetry.mode != SymmetryMode::Off {
+                self.effects.symmetry.center = Some(pos);
+                return;
+            }
+
+            if inp.key_pressed(Key::X) {
+                std::mem::swap(&mut self.draw.px, &mut self.secondary_px);
+            }
+
+            // selecting doesn't draw anything
+            if self.mode == Mode::Select {
+                self.select_input(inp, pos);
+                return;
+            }
+            if self.mode == Mode::MagicWand {
+                self.magic_wand(inp, pos);
+                return;
+            }
+
+            // the airbrush keeps spraying while held, even if the mouse doesn't move
+            if self.mode == Mode::Airbrush {
+                let px = if inp.pointer.secondary_down() {
+                    self.secondary_px
+                } else {
+                    self.draw.px
                 };
-                let pos = Location::new(self.to_px(pointer_pos.x), self.to_px(pointer_pos.y));
-                // return if not actually on the image
-                if !r.hovered() {
-                    return; // we don't need to handle it if it's not in focus
+                if inp.pointer.any_down() {
+                    self.record_color(px);
+                    // long pauses between frames shouldn't spray all at once
+                    self.spray(pos, px, inp.stable_dt.min(0.1));
+                    repaint = true;
                 }
+                return;
+            }
 
-                // handle eraser
-                if self.eraser {
-                    if inp.pointer.primary_down() {
-                        self.draw_mouse(
-                            DrawParams {
-                                loc: pos,
-                                size: 20,
-                                px: 0xffffff,
-                            },
-                            App::draw_dot,
-                        );
+            // handle pulling shapes
+            if self.pull_start.is_some() || inp.pointer.secondary_down() && self.mode.pullable() {
+                let pos = self.grid.snap(pos);
+                self.record_color(self.draw.px);
+                self.pull(inp, [pos.x, pos.y]);
+                return;
+            }
+
+            if inp.key_down(Key::D) {
+                self.draw_ngon(
+                    self.draw.at_loc(pos),
+                    3,
+                    self.draw.size.max(1) as f32 * 30.0,
+                    self.draw.size.max(1) as f32 * 30.0,
+                    0.0,
+                );
+            }
+            if inp.key_down(Key::Q) {
+                self.draw_ngon(
+                    self.draw.at_loc(pos),
+                    4,
+                    self.draw.size.max(1) as f32 * 30.0,
+                    self.draw.size.max(1) as f32 * 30.0,
+                    45.0,
+                );
+            }
+            if inp.key_down(Key::K) {
+                self.draw_ngon(
+                    self.draw.at_loc(pos),
+                    0,
+                    self.draw.size.max(1) as f32 * 30.0,
+                    self.draw.size.max(1) as f32 * 30.0,
+                    0.0,
+                );
+            }
+            if matches!(self.mode, Mode::CloneStamp | Mode::Heal) {
+                if inp.modifiers.alt {
+                    if inp.pointer.primary_pressed() {
+                        self.clone_stamp.set_source(pos);
                     }
                     return;
                 }
-
-                // handle pulling shapes
-                if self.pull_start.is_some() || inp.pointer.secondary_down() {
-                    self.pull(inp, [pos.x, pos.y]);
-                    return;
+                if inp.pointer.any_down() && self.last_mouse_pos.is_none() {
+                    self.clone_stamp.start(pos);
                 }
+            }
+            if inp.pointer.any_down() && self.last_mouse_pos.is_none() {
+                self.retouch.start();
+            }
 
-                if inp.key_down(Key::D) {
-                    self.draw_ngon(
-                        self.draw.at_loc(pos),
-                        3,
-                        self.draw.size.max(1) as f32 * 30.0,
-                        self.draw.size.max(1) as f32 * 30.0,
-                        0.0,
-                    );
-                }
-                if inp.key_down(Key::Q) {
-                    self.draw_ngon(
-                        self.draw.at_loc(pos),
-                        4,
-                        self.draw.size.max(1) as f32 * 30.0,
-                        self.draw.size.max(1) as f32 * 30.0,
-                        45.0,
-                    );
+            // a normal draw operation. interpolates unless the operation overrides it
+            // the right mouse button draws with the secondary color
+            let px = if inp.pointer.primary_down() {
+                Some(self.draw.px)
+            } else if inp.pointer.secondary_down() {
+                Some(self.secondary_px)
+            } else {
+                None
+            };
+            if let Some(px) = px {
+                if self.mode.uses_color() {
+                    self.record_color(px);
                 }
-                if inp.key_down(Key::K) {
-                    self.draw_ngon(
-                        self.draw.at_loc(pos),
-                        0,
-                        self.draw.size.max(1) as f32 * 30.0,
-                        self.draw.size.max(1) as f32 * 30.0,
-                        0.0,
-                    );
-                }
-                // a normal draw operation. interpolates unless the operation overrides it
-                if inp.pointer.primary_down() {
-                    let draw = self.draw.at_loc(pos);
-                    if self.mode.run_once() {
-                        // don't interpolate
-                        self.mode.into_fn()(self, draw);
-                    } else {
-                        // interpolate
-                        self.draw_mouse(draw, self.mode.into_fn());
-                    }
+                let draw = self.draw.at_loc(pos).px(px);
+                if self.mode.run_once() {
+                    // don't interpolate
+                    self.mode.into_fn()(self, draw);
                 } else {
-                    self.last_mouse_pos = None;
+                    // interpolate
+                    self.draw_mouse(draw, self.mode.into_fn());
                 }
-            });
+            } else {
+                self.last_mouse_pos = None;
+            }
         });
+        if repaint {
+            ui.ctx().request_repaint();
+        }
     }
 }
 
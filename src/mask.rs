@@ -0,0 +1,423 @@
+//! Operations on the selection mask: grow, shrink, feather, invert and so on
+
+use std::path::Path;
+
+use egui::*;
+use image::{imageops, io::Reader as ImageReader, GrayImage};
+use micro_ndarray::Array;
+
+use crate::{
+    dialog::DialogAction,
+    filter::gaussian_blur,
+    popup::Popup,
+    select::{similar, Combine, Selection},
+    App,
+};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MaskOp {
+    Grow { radius: usize },
+    Shrink { radius: usize },
+    Feather { radius: f32 },
+    Border { width: usize },
+    ColorRange { color: u32, tolerance: u8 },
+}
+
+use MaskOp::*;
+
+impl MaskOp {
+    pub fn name(self) -> &'static str {
+        match self {
+            Grow { .. } => "Grow Selection",
+            Shrink { .. } => "Shrink Selection",
+            Feather { .. } => "Feather Selection",
+            Border { .. } => "Border Selection",
+            ColorRange { .. } => "Select Color Range",
+        }
+    }
+
+    /// The parameter sliders, returns true if any were changed
+    fn params(&mut self, ui: &mut Ui) -> bool {
+        match self {
+            Grow { radius } | Shrink { radius } => ui
+                .add(Slider::new(radius, 1..=200).text("pixels"))
+                .changed(),
+            Feather { radius } => ui
+                .add(Slider::new(radius, 0.5..=100.0).text("radius"))
+                .changed(),
+            Border { width } => ui.add(Slider::new(width, 1..=200).text("width")).changed(),
+            ColorRange { color, tolerance } => {
+                let mut rgb = [16, 8, 0].map(|shift| (*color >> shift) as u8);
+                let mut changed = false;
+                ui.horizontal(|ui| {
+                    ui.label("Color");
+                    if ui.color_edit_button_srgb(&mut rgb).changed() {
+                        let [r, g, b] = rgb.map(|c| c as u32);
+                        *color = r << 16 | g << 8 | b;
+                        changed = true;
+                    }
+                });
+                changed
+                    | ui.add(Slider::new(tolerance, 0..=255).text("tolerance"))
+                        .changed()
+            }
+        }
+    }
+
+    /// Applies this to a mask (or, for color ranges, makes a new one from the image)
+    pub fn apply(self, mask: &Array<u8, 2>, image: &Array<Color32, 2>) -> Array<u8, 2> {
+        let size = mask.size();
+        match self {
+            Grow { radius } => {
+                let dist = distance_squared(mask, true);
+                threshold(size, |i| dist[i] <= (radius * radius) as f32)
+            }
+            Shrink { radius } => {
+                let dist = distance_squared(mask, false);
+                threshold(size, |i| dist[i] > (radius * radius) as f32)
+            }
+            Border { width } => {
+                // half inside and half outside of the edge
+                let outside = distance_squared(mask, true);
+                let inside = distance_squared(mask, false);
+                let half = (width * width) as f32 / 4.0;
+                threshold(size, |i| {
+                    outside[i] <= half.max(1.0) && inside[i] <= half.max(1.0)
+                })
+            }
+            Feather { radius } => {
+                let px: Vec<_> = mask.as_flattened().iter().map(|&m| [m as f32; 4]).collect();
+                let blurred = gaussian_blur(&px, size, radius / 2.0);
+                Array::from_flat(
+                    blurred
+                        .into_iter()
+                        .map(|p| p[0].round().clamp(0.0, 255.0) as u8)
+                        .collect(),
+                    size,
+                )
+                .unwrap()
+            }
+            ColorRange { color, tolerance } => {
+                let col = Color32::from_rgb((color >> 16) as u8, (color >> 8) as u8, color as u8);
+                Array::new_by_enumeration(image.size(), |i| {
+                    if similar(image.as_flattened()[i], col, tolerance) {
+                        255
+                    } else {
+                        0
+                    }
+                })
+            }
+        }
+    }
+}
+
+fn threshold(size: [usize; 2], selected: impl Fn(usize) -> bool) -> Array<u8, 2> {
+    Array::new_by_enumeration(size, |i| if selected(i) { 255 } else { 0 })
+}
+
+/// The squared distance of every pixel to the nearest (mostly) selected pixel,
+/// or to the nearest unselected one if `to_selected` is false
+fn distance_squared(mask: &Array<u8, 2>, to_selected: bool) -> Vec<f32> {
+    const INF: f32 = 1e20;
+    let [w, h] = mask.size();
+    let mut dist: Vec<f32> = mask
+        .as_flattened()
+        .iter()
+        .map(|&m| if (m >= 128) == to_selected { 0.0 } else { INF })
+        .collect();
+    // exact euclidean distances, one dimension at a time (Felzenszwalb & Huttenlocher)
+    let mut column = vec![0.0; h];
+    for x in 0..w {
+        for y in 0..h {
+            column[y] = dist[x + y * w];
+        }
+        let column = distance_1d(&column);
+        for y in 0..h {
+            dist[x + y * w] = column[y];
+        }
+    }
+    for y in 0..h {
+        let row = distance_1d(&dist[y * w..(y + 1) * w]);
+        dist[y * w..(y + 1) * w].copy_from_slice(&row);
+    }
+    dist
+}
+
+/// The lower envelope of the parabolas rooted at each f[q]
+fn distance_1d(f: &[f32]) -> Vec<f32> {
+    let n = f.len();
+    let mut d = vec![0.0; n];
+    if n == 0 {
+        return d;
+    }
+    // the parabolas in the envelope and where each starts to be the lowest
+    let mut v = vec![0; n];
+    let mut z = vec![0.0; n + 1];
+    let mut k = 0;
+    z[0] = f32::NEG_INFINITY;
+    z[1] = f32::INFINITY;
+    let intersection = |q: usize, p: usize| {
+        ((f[q] + (q * q) as f32) - (f[p] + (p * p) as f32)) / (2.0 * q as f32 - 2.0 * p as f32)
+    };
+    for q in 1..n {
+        let mut s = intersection(q, v[k]);
+        while s <= z[k] {
+            k -= 1;
+            s = intersection(q, v[k]);
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = f32::INFINITY;
+    }
+    k = 0;
+    for (q, d) in d.iter_mut().enumerate() {
+        while z[k + 1] < q as f32 {
+            k += 1;
+        }
+        let dq = q as f32 - v[k] as f32;
+        *d = dq * dq + f[v[k]];
+    }
+    d
+}
+
+/// Changes the selection with live feedback, restoring it on cancel
+pub struct MaskDialog {
+    op: MaskOp,
+    combine: Combine,
+    original: Option<Selection>,
+}
+
+impl MaskDialog {
+    pub fn new(app: &mut App, op: MaskOp) -> Self {
+        let this = Self {
+            op,
+            combine: Combine::Replace,
+            original: app.selection.clone(),
+        };
+        this.update(app);
+        this
+    }
+
+    fn update(&self, app: &mut App) {
+        app.selection = self.original.clone();
+        let size = app.image.size();
+        let mask = match self.original {
+            Some(ref selection) => selection.mask.clone(),
+            None => Array::new_with(size, 0),
+        };
+        let mask = self.op.apply(&mask, &app.image);
+        match self.op {
+            ColorRange { .. } => app.combine_selection(mask, self.combine),
+            _ => app.selection = Selection::from_mask(mask),
+        }
+    }
+
+    pub fn show(&mut self, app: &mut App, ctx: &Context) -> bool {
+        let mut open = true;
+        let mut done = false;
+        Window::new(self.op.name())
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let mut changed = self.op.params(ui);
+                if let ColorRange { .. } = self.op {
+                    ui.horizontal(|ui| {
+                        for (combine, name) in [
+                            (Combine::Replace, "Replace"),
+                            (Combine::Add, "Add"),
+                            (Combine::Subtract, "Subtract"),
+                            (Combine::Intersect, "Intersect"),
+                        ] {
+                            changed |= ui.radio_value(&mut self.combine, combine, name).changed();
+                        }
+                    });
+                }
+                if changed {
+                    self.update(app);
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Apply").clicked() {
+                        done = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        app.selection = self.original.clone();
+                        done = true;
+                    }
+                });
+            });
+        if !open {
+            app.selection = self.original.clone();
+        }
+        open && !done
+    }
+}
+
+impl App {
+    pub fn select_menu(&mut self, ui: &mut Ui) {
+        let size = self.image.size();
+        if ui.button("Select All").clicked() {
+            self.selection = Selection::from_mask(Array::new_with(size, 255));
+            ui.close_menu();
+        }
+        if ui.button("Select None").clicked() {
+            self.selection = None;
+            ui.close_menu();
+        }
+        if ui.button("Invert Selection").clicked() {
+            self.selection = match self.selection.take() {
+                Some(selection) => Selection::from_mask(
+                    Array::from_flat(
+                        selection
+                            .mask
+                            .as_flattened()
+                            .iter()
+                            .map(|&m| 255 - m)
+                            .collect(),
+                        size,
+                    )
+                    .unwrap(),
+                ),
+                // nothing selected inverted is everything
+                None => Selection::from_mask(Array::new_with(size, 255)),
+            };
+            ui.close_menu();
+        }
+        ui.separator();
+        let has_selection = self.selection.is_some();
+        for op in [
+            Grow { radius: 5 },
+            Shrink { radius: 5 },
+            Feather { radius: 5.0 },
+            Border { width: 4 },
+        ] {
+            if ui
+                .add_enabled(has_selection, Button::new(format!("{}...", op.name())))
+                .clicked()
+            {
+                self.popup = Some(Popup::Mask(MaskDialog::new(self, op)));
+                ui.close_menu();
+            }
+        }
+        let op = ColorRange {
+            color: self.draw.px,
+            tolerance: 32,
+        };
+        if ui.button(format!("{}...", op.name())).clicked() {
+            self.popup = Some(Popup::Mask(MaskDialog::new(self, op)));
+            ui.close_menu();
+        }
+        ui.separator();
+        if ui.button("Load Mask...").clicked() {
+            self.file_dialog(false, DialogAction::LoadMask);
+            ui.close_menu();
+        }
+        if ui
+            .add_enabled(has_selection, Button::new("Save Mask..."))
+            .clicked()
+        {
+            self.file_dialog(true, DialogAction::SaveMask);
+            ui.close_menu();
+        }
+    }
+
+    /// Loads a grayscale image as the selection, white is selected. It is stretched to the canvas size.
+    pub fn load_mask(&mut self, path: &Path) -> Result<(), String> {
+        let mask = ImageReader::open(path)
+            .map_err(|e| e.to_string())?
+            .decode()
+            .map_err(|e| e.to_string())?;
+        let [w, h] = self.image.size();
+        let mut mask = mask.to_luma8();
+        if mask.dimensions() != (w as u32, h as u32) {
+            mask = imageops::resize(&mask, w as u32, h as u32, imageops::FilterType::Triangle);
+        }
+        self.selection = Selection::from_mask(Array::from_flat(mask.into_raw(), [w, h]).unwrap());
+        Ok(())
+    }
+
+    /// Saves the selection as a grayscale image, white is selected
+    pub fn save_mask(&mut self, path: &Path) -> Result<(), String> {
+        let Some(ref selection) = self.selection else {
+            return Ok(());
+        };
+        let [w, h] = selection.mask.size();
+        GrayImage::from_raw(w as u32, h as u32, selection.mask.as_flattened().to_vec())
+            .unwrap()
+            .save(path)
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INF: f32 = 1e20;
+
+    /// A mask with a single selected rectangle
+    fn rect_mask(size: [usize; 2], min: [usize; 2], max: [usize; 2]) -> Array<u8, 2> {
+        Array::new_by_enumeration(size, |i| {
+            let (x, y) = (i % size[0], i / size[0]);
+            if (min[0]..max[0]).contains(&x) && (min[1]..max[1]).contains(&y) {
+                255
+            } else {
+                0
+            }
+        })
+    }
+
+    fn selected(mask: &Array<u8, 2>) -> usize {
+        mask.as_flattened().iter().filter(|&&m| m >= 128).count()
+    }
+
+    #[test]
+    fn distance_1d_squares_the_distance() {
+        assert_eq!(
+            distance_1d(&[INF, 0.0, INF, INF, INF]),
+            [1.0, 0.0, 1.0, 4.0, 9.0]
+        );
+        assert_eq!(
+            distance_1d(&[0.0, INF, INF, INF, 0.0]),
+            [0.0, 1.0, 4.0, 1.0, 0.0]
+        );
+        // the lower of a near high start and a far low one
+        assert_eq!(
+            distance_1d(&[INF, 9.0, INF, INF, 0.0]),
+            [10.0, 9.0, 4.0, 1.0, 0.0]
+        );
+        assert!(distance_1d(&[]).is_empty());
+    }
+
+    #[test]
+    fn grow_and_shrink() {
+        let image = Array::new_with([10, 10], Color32::BLACK);
+        let mask = rect_mask([10, 10], [3, 3], [7, 7]);
+        let grown = Grow { radius: 1 }.apply(&mask, &image);
+        // a plus shaped step outwards, corners are further than 1 away
+        assert_eq!(selected(&grown), 16 + 4 * 4);
+        assert_eq!(grown[[2, 3]], 255);
+        assert_eq!(grown[[2, 2]], 0);
+        let shrunk = Shrink { radius: 1 }.apply(&mask, &image);
+        assert_eq!(selected(&shrunk), 4);
+        assert_eq!(shrunk[[4, 4]], 255);
+        assert_eq!(shrunk[[3, 4]], 0);
+        // shrinking everything away and growing from nothing both leave nothing
+        let none = Array::new_with([10, 10], 0);
+        assert_eq!(selected(&Shrink { radius: 2 }.apply(&shrunk, &image)), 0);
+        assert_eq!(selected(&Grow { radius: 3 }.apply(&none, &image)), 0);
+    }
+
+    #[test]
+    fn feather_keeps_the_inside_and_fades_the_edge() {
+        let image = Array::new_with([20, 20], Color32::BLACK);
+        let mask = rect_mask([20, 20], [5, 5], [15, 15]);
+        let feathered = Feather { radius: 2.0 }.apply(&mask, &image);
+        assert_eq!(feathered[[10, 10]], 255);
+        assert_eq!(feathered[[0, 0]], 0);
+        let edge = feathered[[5, 10]];
+        assert!(edge > 128 && edge < 255, "{edge}");
+        assert!(feathered[[4, 10]] > 0 && feathered[[4, 10]] < 128);
+    }
+}
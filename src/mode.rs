@@ -2,24 +2,61 @@ use egui::*;
 
 use crate::{draw::DrawParams, App};
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Mode {
     Paintbrush,
+    Airbrush,
+    Line,
     Triangle,
     Square,
     Circle,
     Fill,
+    Eraser,
+    CloneStamp,
+    Heal,
+    Smudge,
+    Blur,
+    Sharpen,
+    Dodge,
+    Burn,
+    Select,
+    MagicWand,
 }
 
 use Mode::*;
 
 impl Mode {
+    pub const ALL: [Mode; 17] = [
+        Paintbrush, Airbrush, Line, Triangle, Square, Circle, Fill, Eraser, CloneStamp, Heal,
+        Smudge, Blur, Sharpen, Dodge, Burn, Select, MagicWand,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Paintbrush => "Paintbrush",
+            Airbrush => "Airbrush",
+            Line => "Line",
+            Triangle => "Triangle",
+            Square => "Square",
+            Circle => "Circle",
+            Fill => "Fill",
+            Eraser => "Eraser",
+            CloneStamp => "Clone Stamp",
+            Heal => "Healing Brush",
+            Smudge => "Smudge",
+            Blur => "Blur",
+            Sharpen => "Sharpen",
+            Dodge => "Dodge",
+            Burn => "Burn",
+            Select => "Select",
+            MagicWand => "Magic Wand",
+        }
+    }
+
     pub fn menu(app: &mut App, ui: &mut Ui) {
-        ui.radio_value(&mut app.mode, Paintbrush, "Paintbrush");
-        ui.radio_value(&mut app.mode, Triangle, "Triangle");
-        ui.radio_value(&mut app.mode, Square, "Square");
-        ui.radio_value(&mut app.mode, Circle, "Circle");
-        ui.radio_value(&mut app.mode, Fill, "Fill");
+        for mode in Self::ALL {
+            ui.radio_value(&mut app.mode, mode, mode.name());
+        }
     }
 
     /// Some things shouldn't be interpolated and only run once
@@ -31,6 +68,28 @@ impl Mode {
         }
     }
 
+    /// Shapes can be pulled out with the right mouse button, the other modes use it to draw with the secondary color
+    pub fn pullable(self) -> bool {
+        matches!(self, Line | Triangle | Square | Circle)
+    }
+
+    /// Whether this paints with the current color, or only changes the pixels that are there
+    pub fn uses_color(self) -> bool {
+        !matches!(
+            self,
+            Eraser
+                | CloneStamp
+                | Heal
+                | Smudge
+                | Blur
+                | Sharpen
+                | Dodge
+                | Burn
+                | Select
+                | MagicWand
+        )
+    }
+
     /// universalizes the mode into a single function with pre-set size
     pub fn into_fn_sized(self, radius_x: f32, radius_y: f32) -> fn(&mut App, DrawParams) {
         static mut RADIUS: (f32, f32) = (0.0, 0.0);
@@ -39,10 +98,23 @@ impl Mode {
             RADIUS = (radius_x, radius_y); // need this for compatible match arms
             match self {
                 Paintbrush => App::draw_dot,
+                Line => |this, draw| {
+                    let (x, y) = (RADIUS.0 as isize, RADIUS.1 as isize);
+                    this.draw_line(draw.offset(-x, -y), draw.offset(x, y), App::draw_dot)
+                },
                 Triangle => |this, draw| this.draw_ngon(draw, 3, RADIUS.0, -RADIUS.1, 180.0),
                 Square => |this, draw| this.draw_ngon(draw, 4, RADIUS.0, -RADIUS.1, 45.0),
                 Circle => |this, draw| this.draw_ngon(draw, 0, RADIUS.0, -RADIUS.1, 0.0),
                 Fill => |this, draw| this.fill(draw),
+                Eraser => App::erase_dot,
+                CloneStamp => App::clone_dot,
+                Heal => App::heal_dot,
+                Smudge => App::smudge_dot,
+                Blur => App::blur_dot,
+                Sharpen => App::sharpen_dot,
+                Dodge => App::dodge_dot,
+                Burn => App::burn_dot,
+                Airbrush | Select | MagicWand => |_, _| {},
             }
         }
     }
@@ -51,6 +123,10 @@ impl Mode {
     pub fn into_fn(self) -> fn(&mut App, DrawParams) {
         match self {
             Paintbrush => App::draw_dot,
+            Line => |this, draw| {
+                let len = this.draw.size.max(1) as isize * 30;
+                this.draw_line(draw.offset(-len, 0), draw.offset(len, 0), App::draw_dot)
+            },
             Triangle => |this, draw| {
                 this.draw_ngon(
                     draw,
@@ -79,6 +155,16 @@ impl Mode {
                 )
             },
             Fill => |this, draw| this.fill(draw),
+            Eraser => App::erase_dot,
+            CloneStamp => App::clone_dot,
+            Heal => App::heal_dot,
+            Smudge => App::smudge_dot,
+            Blur => App::blur_dot,
+            Sharpen => App::sharpen_dot,
+            Dodge => App::dodge_dot,
+            Burn => App::burn_dot,
+            // handled separately, see App::spray, App::select_input and App::magic_wand
+            Airbrush | Select | MagicWand => |_, _| {},
         }
     }
 }
@@ -0,0 +1,282 @@
+//! OpenRaster (.ora) files, to exchange images with Krita, MyPaint and GIMP.
+//!
+//! The canvas is a single image, so the layer stack is flattened when opening
+//! (with each layer's visibility, opacity, offset and blend mode) and saved as one layer.
+
+use std::{fs, path::Path};
+
+use egui::Color32;
+use image::imageops;
+use micro_ndarray::Array;
+
+use crate::{
+    blend::BlendMode,
+    io::{encode_png, from_rgba_image, to_rgba_image},
+    zip::{read_zip, ZipWriter},
+    App,
+};
+
+const MIMETYPE: &str = "image/openraster";
+const THUMBNAIL_SIZE: u32 = 256;
+/// Larger images are taken to be a broken file rather than allocated
+const MAX_SIZE: usize = 1 << 15;
+
+pub fn is_ora(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("ora"))
+}
+
+fn blend_mode(composite_op: &str) -> BlendMode {
+    match composite_op {
+        "svg:multiply" => BlendMode::Multiply,
+        "svg:screen" => BlendMode::Screen,
+        "svg:overlay" => BlendMode::Overlay,
+        "svg:darken" => BlendMode::Darken,
+        "svg:lighten" => BlendMode::Lighten,
+        "svg:plus" => BlendMode::Add,
+        "svg:difference" => BlendMode::Difference,
+        "svg:color" => BlendMode::Color,
+        "svg:luminosity" => BlendMode::Luminosity,
+        // svg:src-over and the ones there is no equivalent for
+        _ => BlendMode::Normal,
+    }
+}
+
+/// One `<layer>` from stack.xml
+struct Layer {
+    src: String,
+    x: isize,
+    y: isize,
+    opacity: f32,
+    visible: bool,
+    blend_mode: BlendMode,
+}
+
+/// The value of an attribute in a tag like `<layer src="a.png" x="0">`
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    while let Some(i) = rest.find(name) {
+        let before = rest[..i].chars().last();
+        rest = &rest[i + name.len()..];
+        if !before.is_some_and(char::is_whitespace) {
+            continue;
+        }
+        let Some(value) = rest.trim_start().strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim_start();
+        let quote = value.chars().next()?;
+        let value = &value[quote.len_utf8()..];
+        let end = value.find(quote)?;
+        return Some(
+            value[..end]
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&apos;", "'")
+                .replace("&amp;", "&"),
+        );
+    }
+    None
+}
+
+/// The size of the image and its layers from top to bottom.
+/// Nested stacks are flattened, passing their visibility and opacity down to the layers inside.
+fn parse_stack(xml: &str) -> Option<([usize; 2], Vec<Layer>)> {
+    let image_tag = &xml[xml.find("<image")?..];
+    let image_tag = &image_tag[..image_tag.find('>')?];
+    let size = [
+        attribute(image_tag, "w")?.parse().ok()?,
+        attribute(image_tag, "h")?.parse().ok()?,
+    ];
+    // the visibility and opacity of the stacks around the current tag
+    let mut groups = vec![(true, 1.0)];
+    let mut layers = Vec::new();
+    for tag in xml.split('<').skip(1) {
+        let tag = &tag[..tag.find('>')?];
+        let number = |name: &str, default| {
+            attribute(tag, name)
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        };
+        let visible = attribute(tag, "visibility").is_none_or(|v| v != "hidden");
+        let (group_visible, group_opacity) = *groups.last()?;
+        let name = tag.split(char::is_whitespace).next()?.trim_end_matches('/');
+        match name {
+            // an empty `<stack/>` has nothing to pass down
+            "stack" if !tag.ends_with('/') => groups.push((
+                group_visible && visible,
+                group_opacity * number("opacity", 1.0),
+            )),
+            "/stack" => {
+                groups.pop();
+                if groups.is_empty() {
+                    return None;
+                }
+            }
+            "layer" => layers.push(Layer {
+                src: attribute(tag, "src")?,
+                x: number("x", 0.0) as isize,
+                y: number("y", 0.0) as isize,
+                opacity: group_opacity * number("opacity", 1.0),
+                visible: group_visible && visible,
+                blend_mode: blend_mode(&attribute(tag, "composite-op").unwrap_or_default()),
+            }),
+            _ => {}
+        }
+    }
+    Some((size, layers))
+}
+
+impl App {
+    pub fn load_ora(&mut self, path: &Path) -> Result<(), String> {
+        let bytes = fs::read(path).map_err(|e| e.to_string())?;
+        let files = read_zip(&bytes)?;
+        let file = |name: &str| {
+            files
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, data)| data.as_slice())
+                .ok_or_else(|| format!("{name} is missing from the file"))
+        };
+        let decode = |name: &str| -> Result<Array<Color32, 2>, String> {
+            let image = image::load_from_memory(file(name)?).map_err(|e| format!("{name}: {e}"))?;
+            Ok(from_rgba_image(&image.to_rgba8()))
+        };
+        let flatten = || -> Result<Array<Color32, 2>, String> {
+            let xml = String::from_utf8_lossy(file("stack.xml")?).into_owned();
+            let (size, layers) = parse_stack(&xml).ok_or("stack.xml can't be read")?;
+            if size[0] == 0 || size[1] == 0 || size[0] > MAX_SIZE || size[1] > MAX_SIZE {
+                return Err(format!("the image size {}x{} is invalid", size[0], size[1]));
+            }
+            let mut image = Array::new_with(size, Color32::TRANSPARENT);
+            for layer in layers.iter().rev().filter(|layer| layer.visible) {
+                let pixels = decode(&layer.src)?;
+                for ([x, y], &top) in pixels.iter() {
+                    let (x, y) = (x as isize + layer.x, y as isize + layer.y);
+                    if let Some(px) = image.get_mut([x as usize, y as usize]) {
+                        *px = layer.blend_mode.blend(*px, top, layer.opacity);
+                    }
+                }
+            }
+            Ok(image)
+        };
+        // the flattened copy every file has, in case the stack can't be read
+        let image = flatten().or_else(|e| {
+            decode("mergedimage.png").map_err(|merged| format!("{e}, and {merged}"))
+        })?;
+        self.replace_image(image);
+        self.reset_animation();
+        Ok(())
+    }
+
+    pub fn save_ora(&mut self, path: &Path) -> Result<(), String> {
+        let [w, h] = self.image.size();
+        let image = to_rgba_image(&self.image);
+        let png = encode_png(image.clone().into());
+        // fits in a square of THUMBNAIL_SIZE, keeping the aspect ratio
+        let scale = (THUMBNAIL_SIZE as f32 / w.max(h).max(1) as f32).min(1.0);
+        let thumbnail = imageops::thumbnail(
+            &image,
+            ((w as f32 * scale).round() as u32).max(1),
+            ((h as f32 * scale).round() as u32).max(1),
+        );
+        let stack = format!(
+            "<?xml version='1.0' encoding='UTF-8'?>\n\
+             <image version=\"0.0.3\" w=\"{w}\" h=\"{h}\">\n\
+             <stack>\n\
+             <layer name=\"Canvas\" src=\"data/canvas.png\" x=\"0\" y=\"0\" opacity=\"1.0\" \
+             visibility=\"visible\" composite-op=\"svg:src-over\"/>\n\
+             </stack>\n\
+             </image>\n"
+        );
+
+        let mut zip = ZipWriter::new();
+        // the mimetype has to be first and uncompressed, so the file can be recognized
+        zip.add("mimetype", MIMETYPE.as_bytes());
+        zip.add("stack.xml", stack.as_bytes());
+        zip.add("data/canvas.png", &png);
+        zip.add("mergedimage.png", &png);
+        zip.add("Thumbnails/thumbnail.png", &encode_png(thumbnail.into()));
+        fs::write(path, zip.finish()).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attribute_quoting() {
+        let tag = r#"layer src = 'a "b".png' name="x &amp; &lt;y&gt;" opacity=0.5"#;
+        assert_eq!(attribute(tag, "src").as_deref(), Some("a \"b\".png"));
+        assert_eq!(attribute(tag, "name").as_deref(), Some("x & <y>"));
+        // not valid XML, so there is no value
+        assert_eq!(attribute(tag, "opacity"), None);
+        assert_eq!(attribute(tag, "visibility"), None);
+    }
+
+    #[test]
+    fn attribute_order() {
+        // `x` also appears inside other names and values before the attribute itself
+        let tag = r#"layer src="x.png" name="x" xx="1" x="3" y="4""#;
+        assert_eq!(attribute(tag, "x").as_deref(), Some("3"));
+        assert_eq!(attribute(tag, "y").as_deref(), Some("4"));
+        let tag = r#"layer y="4" x="3" src="x.png""#;
+        assert_eq!(attribute(tag, "x").as_deref(), Some("3"));
+        assert_eq!(attribute(tag, "src").as_deref(), Some("x.png"));
+    }
+
+    #[test]
+    fn self_closing_and_paired_tags() {
+        let (size, layers) = parse_stack(
+            r#"<?xml version="1.0"?>
+            <image w="4" h="3"><stack>
+                <layer src="a.png" x="1" y="-2"/>
+                <layer src="b.png" composite-op="svg:multiply"></layer>
+                <stack/>
+                <layer src="c.png" opacity="0.25" visibility="hidden" />
+            </stack></image>"#,
+        )
+        .unwrap();
+        assert_eq!(size, [4, 3]);
+        let srcs: Vec<&str> = layers.iter().map(|l| l.src.as_str()).collect();
+        assert_eq!(srcs, ["a.png", "b.png", "c.png"]);
+        assert_eq!((layers[0].x, layers[0].y), (1, -2));
+        assert!(layers[1].blend_mode == BlendMode::Multiply);
+        assert_eq!(layers[2].opacity, 0.25);
+        assert!(layers[0].visible && layers[1].visible && !layers[2].visible);
+    }
+
+    #[test]
+    fn nested_groups() {
+        let (_, layers) = parse_stack(
+            r#"<image w="1" h="1"><stack>
+                <stack opacity="0.5">
+                    <layer src="half.png" opacity="0.5"/>
+                    <stack visibility="hidden">
+                        <layer src="hidden.png"/>
+                        <stack><layer src="deep.png"/></stack>
+                    </stack>
+                    <layer src="after.png"/>
+                </stack>
+                <layer src="top.png"/>
+            </stack></image>"#,
+        )
+        .unwrap();
+        let layer = |src: &str| layers.iter().find(|l| l.src == src).unwrap();
+        assert_eq!(layer("half.png").opacity, 0.25);
+        assert!(!layer("hidden.png").visible);
+        assert!(!layer("deep.png").visible);
+        assert!(layer("after.png").visible);
+        assert_eq!(layer("after.png").opacity, 0.5);
+        assert!(layer("top.png").visible);
+        assert_eq!(layer("top.png").opacity, 1.0);
+    }
+
+    #[test]
+    fn unbalanced_stacks() {
+        assert!(parse_stack(r#"<image w="1" h="1"></stack></stack>"#).is_none());
+        assert!(parse_stack(r#"<image h="1"><stack></stack>"#).is_none());
+    }
+}
@@ -0,0 +1,693 @@
+//! User palettes (swatches) and the palette file formats
+
+use std::{fs, path::Path};
+
+use egui::*;
+
+use crate::{
+    color::{lab_to_rgb, rgb_to_hsl, ColorConvert},
+    dialog::DialogAction,
+    quantize::{color_counts, nearest, palette, selected_color_counts, Quantizer, Rgb},
+    App,
+};
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Swatch {
+    pub name: String,
+    pub color: u32,
+}
+
+impl Swatch {
+    pub fn new(name: impl Into<String>, color: u32) -> Self {
+        Self {
+            name: name.into(),
+            color,
+        }
+    }
+
+    fn rgb(&self) -> [u8; 3] {
+        [
+            (self.color >> 16) as u8,
+            (self.color >> 8) as u8,
+            self.color as u8,
+        ]
+    }
+
+    fn from_rgb(name: impl Into<String>, [r, g, b]: [u8; 3]) -> Self {
+        Self::new(name, (r as u32) << 16 | (g as u32) << 8 | b as u32)
+    }
+}
+
+#[derive(Clone)]
+pub struct Palette {
+    pub name: String,
+    pub swatches: Vec<Swatch>,
+}
+
+impl Default for Palette {
+    /// the colors this app always had
+    fn default() -> Self {
+        Self {
+            name: "Default".to_owned(),
+            swatches: vec![
+                Swatch::new("Black", 0x000000),
+                Swatch::new("White", 0xffffff),
+                Swatch::new("Red", 0xff0000),
+                Swatch::new("Green", 0x00ff00),
+                Swatch::new("Blue", 0x0000ff),
+                Swatch::new("Yellow", 0xffff00),
+                Swatch::new("Orange", 0xff8000),
+                Swatch::new("Brown", 0x654321),
+                Swatch::new("Aqua", 0x00ffff),
+                Swatch::new("Purple", 0xff00ff),
+            ],
+        }
+    }
+}
+
+impl Palette {
+    /// Loads a palette, the format is chosen by the file extension (.gpl, .ase, .pal, anything else is hex)
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let name = path
+            .file_stem()
+            .map_or("Palette".into(), |s| s.to_string_lossy().into_owned());
+        let bytes = fs::read(path).map_err(|e| e.to_string())?;
+        let text = || String::from_utf8_lossy(&bytes).into_owned();
+        let mut palette = match extension(path).as_str() {
+            "gpl" => Self::from_gpl(&text())?,
+            "ase" => Self::from_ase(&bytes)?,
+            "pal" => Self::from_pal(&text())?,
+            _ => Self::from_hex(&text())?,
+        };
+        if palette.name.is_empty() {
+            palette.name = name;
+        }
+        Ok(palette)
+    }
+
+    /// Saves the palette, the format is chosen by the file extension (.gpl, .ase, .pal, anything else is hex)
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let bytes = match extension(path).as_str() {
+            "gpl" => self.to_gpl().into_bytes(),
+            "ase" => self.to_ase(),
+            "pal" => self.to_pal().into_bytes(),
+            _ => self.to_hex().into_bytes(),
+        };
+        fs::write(path, bytes).map_err(|e| e.to_string())
+    }
+
+    /// GIMP palette: a header, then "r g b name" per line
+    pub fn from_gpl(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+        if lines.next().map(str::trim) != Some("GIMP Palette") {
+            return Err("not a GIMP palette".to_owned());
+        }
+        let mut palette = Self {
+            name: String::new(),
+            swatches: Vec::new(),
+        };
+        for line in lines {
+            let line = line.trim();
+            if let Some(name) = line.strip_prefix("Name:") {
+                palette.name = name.trim().to_owned();
+                continue;
+            }
+            if line.is_empty() || line.starts_with('#') || line.starts_with("Columns:") {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let mut channel = || -> Result<u8, String> {
+                parts
+                    .next()
+                    .and_then(|c| c.parse().ok())
+                    .ok_or_else(|| format!("invalid color: {line}"))
+            };
+            let rgb = [channel()?, channel()?, channel()?];
+            let name = parts.collect::<Vec<_>>().join(" ");
+            palette.swatches.push(Swatch::from_rgb(name, rgb));
+        }
+        Ok(palette)
+    }
+
+    pub fn to_gpl(&self) -> String {
+        let mut text = format!("GIMP Palette\nName: {}\nColumns: 8\n#\n", self.name);
+        for swatch in &self.swatches {
+            let [r, g, b] = swatch.rgb();
+            text += &format!("{r:3} {g:3} {b:3}\t{}\n", swatch.name);
+        }
+        text
+    }
+
+    /// JASC (Paint Shop Pro) palette: a header, the count, then "r g b" per line
+    pub fn from_pal(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().map(str::trim);
+        if lines.next() != Some("JASC-PAL") {
+            return Err("not a JASC palette".to_owned());
+        }
+        // version and count
+        lines.next();
+        lines.next();
+        let swatches = lines
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let rgb: Vec<u8> = line
+                    .split_whitespace()
+                    .map(|c| c.parse().map_err(|_| format!("invalid color: {line}")))
+                    .collect::<Result<_, _>>()?;
+                match rgb[..] {
+                    [r, g, b, ..] => Ok(Swatch::from_rgb(String::new(), [r, g, b])),
+                    _ => Err(format!("invalid color: {line}")),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            name: String::new(),
+            swatches,
+        })
+    }
+
+    pub fn to_pal(&self) -> String {
+        let mut text = format!("JASC-PAL\n0100\n{}\n", self.swatches.len());
+        for swatch in &self.swatches {
+            let [r, g, b] = swatch.rgb();
+            text += &format!("{r} {g} {b}\n");
+        }
+        text
+    }
+
+    /// One rrggbb color per line, with or without a #
+    pub fn from_hex(text: &str) -> Result<Self, String> {
+        let swatches = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with(';'))
+            .map(|line| {
+                let hex = line.trim_start_matches('#');
+                // from_str_radix would also take a sign
+                if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(format!("invalid color: {line}"));
+                }
+                let color = u32::from_str_radix(hex, 16).map_err(|e| e.to_string())?;
+                Ok(Swatch::new(String::new(), color))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            name: String::new(),
+            swatches,
+        })
+    }
+
+    pub fn to_hex(&self) -> String {
+        self.swatches
+            .iter()
+            .map(|swatch| format!("{:06x}\n", swatch.color))
+            .collect()
+    }
+
+    /// Adobe Swatch Exchange: big endian binary blocks, groups are flattened
+    pub fn from_ase(bytes: &[u8]) -> Result<Self, String> {
+        let err = || "invalid ASE file".to_owned();
+        let mut reader = Reader { bytes, pos: 0 };
+        if reader.take(4).ok_or_else(err)? != b"ASEF" {
+            return Err("not an ASE file".to_owned());
+        }
+        // version
+        reader.take(4).ok_or_else(err)?;
+        let blocks = reader.u32().ok_or_else(err)?;
+        let mut swatches = Vec::new();
+        for _ in 0..blocks {
+            let kind = reader.u16().ok_or_else(err)?;
+            let len = reader.u32().ok_or_else(err)? as usize;
+            let block = reader.take(len).ok_or_else(err)?;
+            // only colors, not group starts (0xc001) or ends (0xc002)
+            if kind != 0x0001 {
+                continue;
+            }
+            let mut block = Reader {
+                bytes: block,
+                pos: 0,
+            };
+            let name_len = block.u16().ok_or_else(err)? as usize;
+            let name: Vec<u16> = (0..name_len)
+                .map(|_| block.u16().ok_or_else(err))
+                .collect::<Result<_, _>>()?;
+            let name = String::from_utf16_lossy(&name)
+                .trim_end_matches('\0')
+                .to_owned();
+            let model = block.take(4).ok_or_else(err)?;
+            let mut v = || block.f32().ok_or_else(err);
+            let rgb = match model {
+                b"RGB " => [v()?, v()?, v()?],
+                b"Gray" => [v()?; 3],
+                b"CMYK" => {
+                    let [c, m, y, k] = [v()?, v()?, v()?, v()?];
+                    [c, m, y].map(|c| (1.0 - c) * (1.0 - k))
+                }
+                // L is stored as 0..1
+                b"LAB " => lab_to_rgb([v()? * 100.0, v()?, v()?]),
+                _ => continue,
+            };
+            swatches.push(Swatch::new(name, rgb.into_color()));
+        }
+        Ok(Self {
+            name: String::new(),
+            swatches,
+        })
+    }
+
+    pub fn to_ase(&self) -> Vec<u8> {
+        let mut out = b"ASEF".to_vec();
+        out.extend(1u16.to_be_bytes());
+        out.extend(0u16.to_be_bytes());
+        out.extend((self.swatches.len() as u32).to_be_bytes());
+        for swatch in &self.swatches {
+            let mut block = Vec::new();
+            // name length includes the null terminator
+            let name: Vec<u16> = swatch.name.encode_utf16().chain([0]).collect();
+            block.extend((name.len() as u16).to_be_bytes());
+            name.iter().for_each(|c| block.extend(c.to_be_bytes()));
+            block.extend(b"RGB ");
+            swatch
+                .color
+                .into_colorf()
+                .iter()
+                .for_each(|c| block.extend(c.to_be_bytes()));
+            // "normal" color, not global or spot
+            block.extend(2u16.to_be_bytes());
+
+            out.extend(1u16.to_be_bytes());
+            out.extend((block.len() as u32).to_be_bytes());
+            out.extend(block);
+        }
+        out
+    }
+
+    /// The swatch panel: click to use a color, right click for more
+    pub fn panel(app: &mut App, ui: &mut Ui) {
+        if !app.recent_colors.is_empty() {
+            ui.label("Recent");
+            ui.horizontal_wrapped(|ui| {
+                ui.spacing_mut().item_spacing = vec2(2.0, 2.0);
+                for color in app.recent_colors.clone() {
+                    let response = color_square(ui, color, color == app.draw.px)
+                        .on_hover_text(format!("#{color:06x}"));
+                    if response.clicked() {
+                        app.draw.px = color;
+                    }
+                    if response.secondary_clicked() {
+                        app.secondary_px = color;
+                    }
+                }
+            });
+            ui.separator();
+        }
+        ui.heading(&app.palette.name);
+        ui.horizontal(|ui| {
+            if ui.button("Load...").clicked() {
+                app.file_dialog(false, DialogAction::LoadPalette);
+            }
+            if ui.button("Save...").clicked() {
+                app.file_dialog(true, DialogAction::SavePalette);
+            }
+            if ui
+                .button("+")
+                .on_hover_text("Add the current color")
+                .clicked()
+            {
+                app.palette.swatches.push(Swatch::new("", app.draw.px));
+            }
+        });
+        ui.separator();
+        let mut remove = None;
+        let mut swap = None;
+        let len = app.palette.swatches.len();
+        ScrollArea::vertical().show(ui, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                ui.spacing_mut().item_spacing = vec2(2.0, 2.0);
+                for (i, swatch) in app.palette.swatches.iter_mut().enumerate() {
+                    let response = color_square(ui, swatch.color, swatch.color == app.draw.px);
+                    let response = if swatch.name.is_empty() {
+                        response.on_hover_text(format!("#{:06x}", swatch.color))
+                    } else {
+                        response.on_hover_text(format!("{} #{:06x}", swatch.name, swatch.color))
+                    };
+                    if response.clicked() {
+                        app.draw.px = swatch.color;
+                    }
+                    response.context_menu(|ui| {
+                        ui.text_edit_singleline(&mut swatch.name);
+                        if ui.button("Use as secondary color").clicked() {
+                            app.secondary_px = swatch.color;
+                            ui.close_menu();
+                        }
+                        if ui.button("Set to current color").clicked() {
+                            swatch.color = app.draw.px;
+                            ui.close_menu();
+                        }
+                        if i > 0 && ui.button("Move left").clicked() {
+                            swap = Some((i, i - 1));
+                            ui.close_menu();
+                        }
+                        if i + 1 < len && ui.button("Move right").clicked() {
+                            swap = Some((i, i + 1));
+                            ui.close_menu();
+                        }
+                        if ui.button("Remove").clicked() {
+                            remove = Some(i);
+                            ui.close_menu();
+                        }
+                    });
+                }
+            });
+        });
+        if let Some((a, b)) = swap {
+            app.palette.swatches.swap(a, b);
+        }
+        if let Some(i) = remove {
+            app.palette.swatches.remove(i);
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Sort {
+    Frequency,
+    Hue,
+}
+
+/// Finds the dominant colors of the image (or the selection) to use as a palette
+pub struct GeneratePaletteDialog {
+    colors: usize,
+    quantizer: Quantizer,
+    sort: Sort,
+    selection_only: bool,
+    /// the found colors and how many pixels are closest to each
+    result: Vec<(Rgb, u32)>,
+}
+
+impl GeneratePaletteDialog {
+    pub fn new(app: &App) -> Self {
+        let mut this = Self {
+            colors: 8,
+            quantizer: Quantizer::KMeans,
+            sort: Sort::Frequency,
+            selection_only: app.selection.is_some(),
+            result: Vec::new(),
+        };
+        this.update(app);
+        this
+    }
+
+    fn update(&mut self, app: &App) {
+        let counts = match app.selection {
+            Some(ref selection) if self.selection_only => {
+                selected_color_counts(&app.image, selection)
+            }
+            _ => color_counts(&app.image),
+        };
+        let palette = palette(&counts, self.colors, self.quantizer);
+        // count each color of the image for the palette color it is closest to
+        let mut usage = vec![0; palette.len()];
+        for &(c, count) in &counts {
+            usage[nearest(&palette, c)] += count;
+        }
+        self.result = palette.into_iter().zip(usage).collect();
+        match self.sort {
+            Sort::Frequency => self
+                .result
+                .sort_by_key(|&(_, count)| std::cmp::Reverse(count)),
+            Sort::Hue => self.result.sort_by(|(a, _), (b, _)| {
+                let hsl = |c: &Rgb| rgb_to_hsl(c.map(|c| c as f32 / 255.0));
+                let ([ha, sa, la], [hb, sb, lb]) = (hsl(a), hsl(b));
+                // grays have no hue, they go first from dark to light
+                (sa > 0.0, ha, la)
+                    .partial_cmp(&(sb > 0.0, hb, lb))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            }),
+        }
+    }
+
+    fn to_palette(&self, app: &App) -> Palette {
+        let name = match app.filename {
+            Some(ref filename) => Path::new(filename)
+                .file_stem()
+                .map_or("Generated".into(), |s| s.to_string_lossy().into_owned()),
+            None => "Generated".to_owned(),
+        };
+        Palette {
+            name,
+            swatches: self
+                .result
+                .iter()
+                .map(|&(c, _)| Swatch::from_rgb(String::new(), c))
+                .collect(),
+        }
+    }
+
+    pub fn show(&mut self, app: &mut App, ctx: &Context) -> bool {
+        let mut open = true;
+        let mut done = false;
+        Window::new("Generate Palette")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let mut changed = ui
+                    .add(Slider::new(&mut self.colors, 2..=64).text("colors"))
+                    .changed();
+                ui.horizontal(|ui| {
+                    changed |= ui
+                        .radio_value(&mut self.quantizer, Quantizer::KMeans, "k-means")
+                        .changed();
+                    changed |= ui
+                        .radio_value(&mut self.quantizer, Quantizer::MedianCut, "Median cut")
+                        .changed();
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Sort by");
+                    changed |= ui
+                        .radio_value(&mut self.sort, Sort::Frequency, "Frequency")
+                        .changed();
+                    changed |= ui.radio_value(&mut self.sort, Sort::Hue, "Hue").changed();
+                });
+                if app.selection.is_some() {
+                    changed |= ui
+                        .checkbox(&mut self.selection_only, "Only the selection")
+                        .changed();
+                }
+                if changed {
+                    self.update(app);
+                }
+                ui.horizontal_wrapped(|ui| {
+                    ui.spacing_mut().item_spacing = vec2(2.0, 2.0);
+                    for &([r, g, b], _) in &self.result {
+                        let (rect, response) =
+                            ui.allocate_exact_size(vec2(20.0, 20.0), Sense::hover());
+                        ui.painter()
+                            .rect_filled(rect, 2.0, Color32::from_rgb(r, g, b));
+                        response.on_hover_text(format!("#{r:02x}{g:02x}{b:02x}"));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Use").clicked() {
+                        app.palette = self.to_palette(app);
+                        done = true;
+                    }
+                    if ui.button("Add to palette").clicked() {
+                        let palette = self.to_palette(app);
+                        app.palette.swatches.extend(palette.swatches);
+                        done = true;
+                    }
+                    if ui.button("Use and save...").clicked() {
+                        app.palette = self.to_palette(app);
+                        app.file_dialog(true, DialogAction::SavePalette);
+                        done = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        done = true;
+                    }
+                });
+            });
+        open && !done
+    }
+}
+
+/// A clickable square of a color, outlined if it is the current one
+fn color_square(ui: &mut Ui, color: u32, current: bool) -> Response {
+    let (rect, response) = ui.allocate_exact_size(vec2(20.0, 20.0), Sense::click());
+    let [r, g, b] = [16, 8, 0].map(|shift| (color >> shift) as u8);
+    ui.painter()
+        .rect_filled(rect, 2.0, Color32::from_rgb(r, g, b));
+    if current {
+        ui.painter()
+            .rect_stroke(rect, 2.0, Stroke::new(2.0, Color32::WHITE));
+    }
+    response
+}
+
+impl App {
+    /// Remembers a color that was drawn with for the recent colors
+    pub fn record_color(&mut self, color: u32) {
+        // this is called every frame while drawing, so it has to be cheap then
+        if self.recent_colors.first() == Some(&color) {
+            return;
+        }
+        self.recent_colors.retain(|&c| c != color);
+        self.recent_colors.insert(0, color);
+        self.recent_colors.truncate(16);
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Reads big endian values from a byte slice
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let slice = self.bytes.get(self.pos..self.pos + len)?;
+        self.pos += len;
+        Some(slice)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.take(2)?.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        Some(f32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn palette() -> Palette {
+        Palette {
+            name: "Test colors".to_owned(),
+            swatches: vec![
+                Swatch::new("Black", 0x000000),
+                Swatch::new("Pale rose", 0xf2c4d0),
+                Swatch::new("", 0x0a7f3c),
+                Swatch::new("White", 0xffffff),
+            ],
+        }
+    }
+
+    /// Saves and loads the palette through a file with the extension
+    fn round_trip(extension: &str) -> Palette {
+        let path =
+            std::env::temp_dir().join(format!("palette-test-{}.{extension}", std::process::id()));
+        palette().save(&path).unwrap();
+        let loaded = Palette::load(&path);
+        fs::remove_file(&path).unwrap();
+        loaded.unwrap()
+    }
+
+    fn colors(palette: &Palette) -> Vec<u32> {
+        palette.swatches.iter().map(|s| s.color).collect()
+    }
+
+    #[test]
+    fn gpl_round_trip() {
+        let loaded = round_trip("gpl");
+        assert_eq!(loaded.name, "Test colors");
+        assert!(loaded.swatches == palette().swatches);
+    }
+
+    #[test]
+    fn ase_round_trip() {
+        assert!(round_trip("ase").swatches == palette().swatches);
+    }
+
+    #[test]
+    fn pal_round_trip() {
+        assert_eq!(colors(&round_trip("pal")), colors(&palette()));
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        let loaded = round_trip("hex");
+        assert_eq!(colors(&loaded), colors(&palette()));
+        // the name comes from the file when the format has none
+        assert!(loaded.name.starts_with("palette-test-"));
+    }
+
+    #[test]
+    fn malformed_gpl() {
+        for text in [
+            "",
+            "JASC-PAL",
+            "GIMP Palette\n12 34",
+            "GIMP Palette\n300 0 0 Red",
+            "GIMP Palette\n-1 0 0",
+            "GIMP Palette\nred green blue",
+        ] {
+            assert!(Palette::from_gpl(text).is_err(), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn malformed_pal() {
+        for text in [
+            "",
+            "GIMP Palette",
+            "JASC-PAL\n0100\n1\n1 2",
+            "JASC-PAL\n0100\n1\n1 2 256",
+            "JASC-PAL\n0100\n1\nx y z",
+        ] {
+            assert!(Palette::from_pal(text).is_err(), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn malformed_hex() {
+        for text in [
+            "12345",
+            "#1234567",
+            "zzzzzz",
+            "+12345",
+            "-12345",
+            "#ff00ff\n12",
+        ] {
+            assert!(Palette::from_hex(text).is_err(), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn malformed_ase() {
+        let file = palette().to_ase();
+        // cut off anywhere, including between blocks
+        for len in 0..file.len() {
+            assert!(Palette::from_ase(&file[..len]).is_err(), "cut at {len}");
+        }
+        let mut wrong_magic = file.clone();
+        wrong_magic[0] = b'X';
+        assert!(Palette::from_ase(&wrong_magic).is_err());
+        // a block claiming to be longer than the file
+        let mut long_block = file.clone();
+        long_block[14..18].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(Palette::from_ase(&long_block).is_err());
+        // a name longer than its block
+        let mut long_name = file;
+        long_name[18..20].copy_from_slice(&u16::MAX.to_be_bytes());
+        assert!(Palette::from_ase(&long_name).is_err());
+    }
+
+    #[test]
+    fn missing_file() {
+        assert!(Palette::load(Path::new("/nonexistent/palette.gpl")).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! Patterns that limit which pixels a brush paints, like a checkerboard or ordered dithering
+
+use std::path::Path;
+
+use egui::*;
+use image::io::Reader as ImageReader;
+use micro_ndarray::Array;
+
+use crate::{dialog::DialogAction, quantize::bayer, App};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LineDirection {
+    Horizontal,
+    Vertical,
+    Diagonal,
+}
+
+#[derive(Clone, Default)]
+pub enum Pattern {
+    /// every pixel is painted
+    #[default]
+    Solid,
+    Checkerboard,
+    /// ordered dithering, `density` (0..1) of the pixels are painted
+    Bayer {
+        order: u32,
+        density: f32,
+    },
+    Lines {
+        spacing: usize,
+        direction: LineDirection,
+    },
+    Crosshatch {
+        spacing: usize,
+    },
+    Dots {
+        spacing: usize,
+    },
+    /// repeats a small image, its light pixels are painted
+    Tile(Array<bool, 2>),
+}
+
+use Pattern::*;
+
+impl Pattern {
+    /// Whether this pixel is painted
+    pub fn allows(&self, x: usize, y: usize) -> bool {
+        match self {
+            Solid => true,
+            Checkerboard => !(x + y).is_multiple_of(2),
+            Bayer { order, density } => bayer(*order, x, y) < *density,
+            Lines { spacing, direction } => {
+                let i = match direction {
+                    LineDirection::Horizontal => y,
+                    LineDirection::Vertical => x,
+                    LineDirection::Diagonal => x + y,
+                };
+                i.is_multiple_of(*spacing)
+            }
+            Crosshatch { spacing } => {
+                (x + y).is_multiple_of(*spacing)
+                    || (x + spacing - y % spacing).is_multiple_of(*spacing)
+            }
+            Dots { spacing } => x.is_multiple_of(*spacing) && y.is_multiple_of(*spacing),
+            Tile(tile) => {
+                let [w, h] = tile.size();
+                tile[[x % w, y % h]]
+            }
+        }
+    }
+
+    /// Loads a tile from an image, light and opaque pixels are painted
+    pub fn load_tile(path: &Path) -> Option<Self> {
+        let tile = ImageReader::open(path)
+            .ok()?
+            .decode()
+            .ok()?
+            .to_luma_alpha8();
+        let (w, h) = tile.dimensions();
+        if w == 0 || h == 0 {
+            return None;
+        }
+        let painted = tile
+            .pixels()
+            .map(|p| p.0[0] >= 128 && p.0[1] >= 128)
+            .collect();
+        Some(Tile(
+            Array::from_flat(painted, [w as usize, h as usize]).unwrap(),
+        ))
+    }
+
+    /// Edits the pattern of the current tool
+    pub fn menu(app: &mut App, ui: &mut Ui) {
+        let pattern = app.effects.patterns.entry(app.mode).or_default();
+        let options = [
+            (Solid, "Solid"),
+            (Checkerboard, "Checkerboard"),
+            (
+                Bayer {
+                    order: 2,
+                    density: 0.5,
+                },
+                "Ordered dither",
+            ),
+            (
+                Lines {
+                    spacing: 4,
+                    direction: LineDirection::Horizontal,
+                },
+                "Lines",
+            ),
+            (Crosshatch { spacing: 4 }, "Crosshatch"),
+            (Dots { spacing: 4 }, "Dots"),
+        ];
+        ui.label("For the current tool:");
+        for (option, name) in options {
+            let selected = std::mem::discriminant(pattern) == std::mem::discriminant(&option);
+            if ui.radio(selected, name).clicked() && !selected {
+                *pattern = option;
+            }
+        }
+        let is_tile = matches!(pattern, Tile(_));
+        if ui.radio(is_tile, "Tile from image...").clicked() {
+            app.file_dialog(false, DialogAction::LoadPatternTile);
+            ui.close_menu();
+            return;
+        }
+
+        ui.separator();
+        match pattern {
+            Bayer { order, density } => {
+                ui.add(Slider::new(order, 1..=3).text("size (2^n)"));
+                ui.add(Slider::new(density, 0.0..=1.0).text("density"));
+            }
+            Lines { spacing, direction } => {
+                ui.add(Slider::new(spacing, 2..=32).text("spacing"));
+                ui.horizontal(|ui| {
+                    ui.radio_value(direction, LineDirection::Horizontal, "Horizontal");
+                    ui.radio_value(direction, LineDirection::Vertical, "Vertical");
+                    ui.radio_value(direction, LineDirection::Diagonal, "Diagonal");
+                });
+            }
+            Crosshatch { spacing } | Dots { spacing } => {
+                ui.add(Slider::new(spacing, 2..=32).text("spacing"));
+            }
+            Solid | Checkerboard | Tile(_) => {}
+        }
+    }
+}
+
+impl App {
+    /// Whether the pattern of the current tool paints this pixel
+    #[inline]
+    pub fn pattern_allows(&self, x: usize, y: usize) -> bool {
+        self.effects
+            .patterns
+            .get(&self.mode)
+            .is_none_or(|pattern| pattern.allows(x, y))
+    }
+
+    pub fn load_pattern_tile(&mut self, path: &Path) {
+        match Pattern::load_tile(path) {
+            Some(tile) => {
+                self.effects.patterns.insert(self.mode, tile);
+            }
+            None => println!("Unable to load this pattern."),
+        }
+    }
+}
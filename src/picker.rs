@@ -0,0 +1,245 @@
+//! The docked color panel: an HSV wheel and sliders for several color spaces
+
+use std::{f32::consts::TAU, ops::RangeInclusive};
+
+use egui::*;
+
+use crate::{
+    color::{parse_color, ColorConvert, Hsl, Hsv, Lab, Oklch},
+    App,
+};
+
+pub struct ColorPanel {
+    pub show: bool,
+    /// kept separately from the color so the hue and saturation aren't lost for grays and black
+    hsv: [f32; 3],
+    text: String,
+    /// the color `hsv` and `text` were last updated for
+    color: u32,
+    /// whether the hue ring (or the triangle) is being dragged
+    dragging_ring: bool,
+}
+
+impl Default for ColorPanel {
+    fn default() -> Self {
+        Self {
+            show: false,
+            hsv: [0.0; 3],
+            text: "#000000".to_owned(),
+            color: 0,
+            dragging_ring: false,
+        }
+    }
+}
+
+impl ColorPanel {
+    /// Updates the cached values if the color was changed somewhere else
+    fn sync(&mut self, color: u32) {
+        if color == self.color {
+            return;
+        }
+        let Hsv([h, s, v]) = color.into_hsv();
+        self.hsv = match (s, v) {
+            (_, 0.0) => [self.hsv[0], self.hsv[1], 0.0],
+            (0.0, _) => [self.hsv[0], 0.0, v],
+            _ => [h, s, v],
+        };
+        self.text = format!("#{color:06x}");
+        self.color = color;
+    }
+
+    /// Sets the color from the hsv values, without recomputing them
+    fn set_hsv(&mut self, app: &mut App) {
+        app.draw.px = Hsv(self.hsv).into_color();
+        self.text = format!("#{:06x}", app.draw.px);
+        self.color = app.draw.px;
+    }
+
+    pub fn panel(app: &mut App, ui: &mut Ui) {
+        // taken out so both can be changed
+        let mut this = std::mem::take(&mut app.color_panel);
+        this.sync(app.draw.px);
+
+        if this.wheel(ui) {
+            this.set_hsv(app);
+        }
+        ui.separator();
+
+        let response = ui.text_edit_singleline(&mut this.text);
+        if response.lost_focus() {
+            match parse_color(&this.text) {
+                Some(color) => app.draw.px = color,
+                None => this.text = format!("#{:06x}", app.draw.px),
+            }
+        }
+        response.on_hover_text("#rrggbb, #rgb or rgb(r, g, b)");
+
+        CollapsingHeader::new("RGB")
+            .default_open(true)
+            .show(ui, |ui| {
+                let mut rgb = [16, 8, 0].map(|shift| (app.draw.px >> shift & 0xff) as u8);
+                let mut changed = false;
+                for (c, name) in rgb.iter_mut().zip(["R", "G", "B"]) {
+                    changed |= ui.add(Slider::new(c, 0..=255).text(name)).changed();
+                }
+                if changed {
+                    let [r, g, b] = rgb.map(|c| c as u32);
+                    app.draw.px = r << 16 | g << 8 | b;
+                }
+            });
+        CollapsingHeader::new("HSV").show(ui, |ui| {
+            if sliders(
+                ui,
+                &mut this.hsv,
+                [0.0..=360.0, 0.0..=1.0, 0.0..=1.0],
+                ["H", "S", "V"],
+            ) {
+                this.set_hsv(app);
+            }
+        });
+        CollapsingHeader::new("HSL").show(ui, |ui| {
+            let Hsl(mut hsl) = app.draw.px.into_hsl();
+            // grays have no hue of their own
+            hsl[0] = this.hsv[0];
+            if sliders(
+                ui,
+                &mut hsl,
+                [0.0..=360.0, 0.0..=1.0, 0.0..=1.0],
+                ["H", "S", "L"],
+            ) {
+                app.draw.px = Hsl(hsl).into_color();
+                this.hsv[0] = hsl[0];
+            }
+        });
+        CollapsingHeader::new("Lab").show(ui, |ui| {
+            let Lab(mut lab) = app.draw.px.into_lab();
+            if sliders(
+                ui,
+                &mut lab,
+                [0.0..=100.0, -128.0..=127.0, -128.0..=127.0],
+                ["L", "a", "b"],
+            ) {
+                app.draw.px = Lab(lab).into_color();
+            }
+        });
+        CollapsingHeader::new("OKLCH").show(ui, |ui| {
+            let Oklch(mut lch) = app.draw.px.into_oklch();
+            if lch[1] < 0.0005 {
+                lch[2] = this.hsv[0];
+            }
+            if sliders(
+                ui,
+                &mut lch,
+                [0.0..=1.0, 0.0..=0.4, 0.0..=360.0],
+                ["L", "C", "H"],
+            ) {
+                app.draw.px = Oklch(lch).into_color();
+            }
+        });
+
+        app.color_panel = this;
+    }
+
+    /// A hue ring around a saturation/value triangle, returns true if the color was changed
+    fn wheel(&mut self, ui: &mut Ui) -> bool {
+        let size = ui.available_width().min(200.0);
+        let (rect, response) = ui.allocate_exact_size(vec2(size, size), Sense::click_and_drag());
+        let center = rect.center();
+        let outer = size / 2.0;
+        let inner = outer * 0.8;
+        let dir = |angle: f32| vec2(angle.cos(), angle.sin());
+        let [h, s, v] = self.hsv;
+        let angle = h.to_radians();
+        // the corners of the triangle: the pure hue, white and black
+        let corners = [0.0, TAU / 3.0, TAU * 2.0 / 3.0].map(|a| center + dir(angle + a) * inner);
+
+        let mut changed = false;
+        if let Some(pos) = response.interact_pointer_pos() {
+            if response.drag_started() || response.clicked() {
+                self.dragging_ring = (pos - center).length() >= inner;
+            }
+            if self.dragging_ring {
+                let d = pos - center;
+                self.hsv[0] = d.y.atan2(d.x).to_degrees().rem_euclid(360.0);
+            } else {
+                let [wh, ww, _] = barycentric(pos, corners);
+                self.hsv[2] = (wh + ww).clamp(0.0, 1.0);
+                self.hsv[1] = if wh + ww > 0.0 {
+                    (wh / (wh + ww)).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+            }
+            changed = true;
+        }
+
+        let painter = ui.painter_at(rect);
+        let mut mesh = Mesh::default();
+        let segments = 90;
+        for i in 0..=segments {
+            let a = i as f32 / segments as f32 * TAU;
+            let color = hsv_color([a.to_degrees(), 1.0, 1.0]);
+            mesh.colored_vertex(center + dir(a) * inner, color);
+            mesh.colored_vertex(center + dir(a) * outer, color);
+            if i > 0 {
+                let j = i * 2;
+                mesh.add_triangle(j - 2, j - 1, j);
+                mesh.add_triangle(j - 1, j, j + 1);
+            }
+        }
+        let first = mesh.vertices.len() as u32;
+        for (pos, color) in
+            corners
+                .into_iter()
+                .zip([hsv_color([h, 1.0, 1.0]), Color32::WHITE, Color32::BLACK])
+        {
+            mesh.colored_vertex(pos, color);
+        }
+        mesh.add_triangle(first, first + 1, first + 2);
+        painter.add(Shape::mesh(mesh));
+
+        let marker = |pos: Pos2| {
+            painter.circle_stroke(pos, 4.0, Stroke::new(2.0, Color32::BLACK));
+            painter.circle_stroke(pos, 4.0, Stroke::new(1.0, Color32::WHITE));
+        };
+        marker(center + dir(angle) * (inner + outer) / 2.0);
+        let [ph, pw, pb] = corners;
+        marker(
+            Pos2::ZERO
+                + ph.to_vec2() * v * s
+                + pw.to_vec2() * v * (1.0 - s)
+                + pb.to_vec2() * (1.0 - v),
+        );
+        changed
+    }
+}
+
+fn hsv_color(hsv: [f32; 3]) -> Color32 {
+    let c = Hsv(hsv).into_color();
+    Color32::from_rgb((c >> 16) as u8, (c >> 8) as u8, c as u8)
+}
+
+/// The weights of the corners of the triangle at `p`, clamped to be inside it
+fn barycentric(p: Pos2, [a, b, c]: [Pos2; 3]) -> [f32; 3] {
+    let (v0, v1, v2) = (b - a, c - a, p - a);
+    let den = v0.x * v1.y - v1.x * v0.y;
+    let wb = (v2.x * v1.y - v1.x * v2.y) / den;
+    let wc = (v0.x * v2.y - v2.x * v0.y) / den;
+    let w = [1.0 - wb - wc, wb, wc].map(|w: f32| w.max(0.0));
+    let total: f32 = w.iter().sum();
+    w.map(|w| w / total)
+}
+
+/// Three labeled sliders, returns true if any were changed
+fn sliders(
+    ui: &mut Ui,
+    values: &mut [f32; 3],
+    ranges: [RangeInclusive<f32>; 3],
+    names: [&str; 3],
+) -> bool {
+    let mut changed = false;
+    for ((value, range), name) in values.iter_mut().zip(ranges).zip(names) {
+        changed |= ui.add(Slider::new(value, range).text(name)).changed();
+    }
+    changed
+}
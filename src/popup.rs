@@ -0,0 +1,188 @@
+//! Windows asking for parameters of an operation (not files, see dialog.rs for those)
+
+use egui::{Color32, Context, Pos2, Rect, Ui, Window};
+use micro_ndarray::Array;
+
+use crate::{
+    adjust::AdjustDialog,
+    export::ExportDialog,
+    filter::FilterDialog,
+    mask::MaskDialog,
+    palette::GeneratePaletteDialog,
+    quantize::ReduceColorsDialog,
+    resize::{CanvasSizeDialog, ScaleDialog},
+    transform::RotateDialog,
+    App,
+};
+
+pub enum Popup {
+    CanvasSize(CanvasSizeDialog),
+    Scale(ScaleDialog),
+    Rotate(RotateDialog),
+    Filter(FilterDialog),
+    Adjust(AdjustDialog),
+    ReduceColors(ReduceColorsDialog),
+    GeneratePalette(GeneratePaletteDialog),
+    Mask(MaskDialog),
+    Export(ExportDialog),
+}
+
+impl App {
+    // called on app update to show the open popup, if any
+    pub fn handle_popups(&mut self, ctx: &Context) {
+        // taken out so the popup can modify the app
+        let Some(mut popup) = self.popup.take() else {
+            return;
+        };
+        // each show returns whether the popup should stay open
+        let open = match popup {
+            Popup::CanvasSize(ref mut dialog) => dialog.show(self, ctx),
+            Popup::Scale(ref mut dialog) => dialog.show(self, ctx),
+            Popup::Rotate(ref mut dialog) => dialog.show(self, ctx),
+            Popup::Filter(ref mut dialog) => dialog.show(self, ctx),
+            Popup::Adjust(ref mut dialog) => dialog.show(self, ctx),
+            Popup::ReduceColors(ref mut dialog) => dialog.show(self, ctx),
+            Popup::GeneratePalette(ref mut dialog) => dialog.show(self, ctx),
+            Popup::Mask(ref mut dialog) => dialog.show(self, ctx),
+            Popup::Export(ref mut dialog) => dialog.show(self, ctx),
+        };
+        if open && self.popup.is_none() {
+            self.popup = Some(popup);
+        }
+    }
+}
+
+/// Shows the result of an operation on the canvas before it is applied.
+/// Only the selection is changed if there is one.
+struct Preview {
+    original: Array<Color32, 2>,
+}
+
+impl Preview {
+    pub fn new(app: &App) -> Self {
+        Self {
+            original: app.image.clone(),
+        }
+    }
+
+    pub fn original(&self) -> &Array<Color32, 2> {
+        &self.original
+    }
+
+    /// Shows `result` (computed from [`Self::original`]) on the canvas
+    pub fn show(&self, app: &mut App, result: Array<Color32, 2>) {
+        app.image = match app.selection {
+            Some(ref selection) => Array::new_by_enumeration(result.size(), |i| {
+                let (new, old) = (result.as_flattened()[i], self.original.as_flattened()[i]);
+                // partially selected pixels get a mix of both
+                match selection.mask.as_flattened().get(i).copied().unwrap_or(0) {
+                    0 => old,
+                    255 => new,
+                    m => lerp_color(old, new, m as f32 / 255.0),
+                }
+            }),
+            None => result,
+        };
+        let size = app.image.size();
+        app.changes.all(Rect::from_min_max(
+            Pos2::ZERO,
+            Pos2::new(size[0] as f32, size[1] as f32),
+        ));
+    }
+
+    /// Puts the original image back
+    pub fn cancel(&self, app: &mut App) {
+        app.image = self.original.clone();
+        let size = app.image.size();
+        app.changes.all(Rect::from_min_max(
+            Pos2::ZERO,
+            Pos2::new(size[0] as f32, size[1] as f32),
+        ));
+    }
+}
+
+/// An operation with parameters, for [`PreviewDialog`]
+pub trait Previewed {
+    fn title(&self) -> &'static str;
+
+    /// Shows the parameters, returns true if any were changed
+    fn params(&mut self, ui: &mut Ui) -> bool;
+
+    /// The operation applied to `original`
+    fn result(&mut self, original: &Array<Color32, 2>) -> Array<Color32, 2>;
+
+    /// Called when the result is kept
+    fn applied(&mut self, _app: &mut App) {}
+}
+
+/// A window with the parameters of an operation, showing its result on the canvas until it is applied or canceled
+pub struct PreviewDialog<T> {
+    pub op: T,
+    live: bool,
+    preview: Preview,
+    /// whether the parameters changed since the result was shown
+    outdated: bool,
+}
+
+impl<T: Previewed> PreviewDialog<T> {
+    pub fn new(app: &App, op: T) -> Self {
+        Self {
+            op,
+            live: true,
+            preview: Preview::new(app),
+            outdated: true,
+        }
+    }
+
+    fn update(&mut self, app: &mut App) {
+        if self.live {
+            let result = self.op.result(self.preview.original());
+            self.preview.show(app, result);
+        } else {
+            self.preview.cancel(app);
+        }
+        self.outdated = false;
+    }
+
+    pub fn show(&mut self, app: &mut App, ctx: &Context) -> bool {
+        let mut open = true;
+        let mut done = false;
+        Window::new(self.op.title())
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                self.outdated |= self.op.params(ui);
+                self.outdated |= ui.checkbox(&mut self.live, "Preview").changed();
+                // the result can be slow to compute, so not while a slider is dragged
+                if self.outdated && !ui.input(|i| i.pointer.any_down()) {
+                    self.update(app);
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Apply").clicked() {
+                        if !self.live || self.outdated {
+                            self.live = true;
+                            self.update(app);
+                        }
+                        self.op.applied(app);
+                        done = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.preview.cancel(app);
+                        done = true;
+                    }
+                });
+            });
+        if !open {
+            self.preview.cancel(app);
+        }
+        open && !done
+    }
+}
+
+/// Mixes two colors, t = 0 is all `a`, t = 1 is all `b`
+pub fn lerp_color(a: Color32, b: Color32, t: f32) -> Color32 {
+    let (a, b) = (a.to_array(), b.to_array());
+    let mix = |i: usize| (a[i] as f32 + (b[i] as f32 - a[i] as f32) * t).round() as u8;
+    Color32::from_rgba_premultiplied(mix(0), mix(1), mix(2), mix(3))
+}
@@ -0,0 +1,347 @@
+//! The native project format: a zip file with the image as PNG layers, the selection, the palette
+//! and a manifest with the settings, so nothing is lost between sessions.
+//!
+//! The manifest is a list of `key = value` lines. Keys that aren't known are skipped and missing
+//! ones keep their current value, so older and newer versions can open each other's files.
+
+use std::{collections::HashMap, fs, path::Path, str::FromStr};
+
+use egui::Color32;
+use image::GrayImage;
+use micro_ndarray::Array;
+
+use crate::{
+    animation::AnimationFrame,
+    blend::BlendMode,
+    draw::Location,
+    io::{encode_png, from_rgba_image, to_rgba_image},
+    mode::Mode,
+    palette::Palette,
+    select::Selection,
+    symmetry::SymmetryMode,
+    zip::{read_zip, ZipWriter},
+    App,
+};
+
+pub const EXTENSION: &str = "paint";
+const FORMAT: &str = "paint-project";
+/// increased whenever the meaning of existing keys changes, new keys don't need a new version
+const VERSION: u32 = 1;
+
+/// Whether a path should be opened and saved as a project
+pub fn is_project(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(EXTENSION))
+}
+
+fn symmetry_mode_name(mode: SymmetryMode) -> &'static str {
+    match mode {
+        SymmetryMode::Off => "off",
+        SymmetryMode::Horizontal => "horizontal",
+        SymmetryMode::Vertical => "vertical",
+        SymmetryMode::Both => "both",
+        SymmetryMode::Radial => "radial",
+    }
+}
+
+/// The manifest values, by key
+struct Manifest(HashMap<String, String>);
+
+impl Manifest {
+    fn parse(text: &str) -> Self {
+        let values = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| {
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .unwrap_or(value);
+                (key.trim().to_owned(), value.to_owned())
+            })
+            .collect();
+        Self(values)
+    }
+
+    fn str(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    fn get<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.str(key)?.parse().ok()
+    }
+
+    /// Sets `value` if the key is there and valid
+    fn read<T: FromStr>(&self, key: &str, value: &mut T) {
+        if let Some(v) = self.get(key) {
+            *value = v;
+        }
+    }
+
+    fn color(&self, key: &str) -> Option<u32> {
+        u32::from_str_radix(self.str(key)?.strip_prefix('#')?, 16).ok()
+    }
+}
+
+impl App {
+    pub fn save_project(&mut self, path: &Path) -> Result<(), String> {
+        let [w, h] = self.image.size();
+        // the files besides the manifest, which is written first once it's complete
+        let mut files = Vec::new();
+        let mut manifest = format!("format = \"{FORMAT}\"\nversion = {VERSION}\n\n");
+        manifest += &format!("width = {w}\nheight = {h}\n");
+        // the canvas is a single layer, the list leaves room for more
+        manifest += "layers = \"layers/0.png\"\n";
+        files.push((
+            "layers/0.png".to_owned(),
+            encode_png(to_rgba_image(&self.image).into()),
+        ));
+        if let Some(ref selection) = self.selection {
+            let mask =
+                GrayImage::from_raw(w as u32, h as u32, selection.mask.as_flattened().to_vec())
+                    .unwrap();
+            files.push(("selection.png".to_owned(), encode_png(mask.into())));
+            manifest += "selection = \"selection.png\"\n";
+        }
+        // the layers are the current frame, the others are only read by versions that know about frames
+        if self.animation.frames.len() > 1 {
+            let (mut names, mut durations) = (Vec::new(), Vec::new());
+            for (i, (image, duration)) in self.animation_frames().into_iter().enumerate() {
+                let name = format!("frames/{i}.png");
+                files.push((name.clone(), encode_png(to_rgba_image(&image).into())));
+                names.push(name);
+                durations.push(duration.to_string());
+            }
+            manifest += &format!("frames = \"{}\"\n", names.join(","));
+            manifest += &format!("frame_durations = \"{}\"\n", durations.join(","));
+            manifest += &format!("current_frame = {}\n", self.animation.current);
+        }
+        files.push(("palette.gpl".to_owned(), self.palette.to_gpl().into_bytes()));
+        manifest += "palette = \"palette.gpl\"\n";
+
+        manifest += "\n# brush\n";
+        manifest += &format!("mode = \"{}\"\n", self.mode.name());
+        manifest += &format!("size = {}\n", self.draw.size);
+        manifest += &format!("primary = \"#{:06x}\"\n", self.draw.px);
+        manifest += &format!("secondary = \"#{:06x}\"\n", self.secondary_px);
+        manifest += &format!("blend_mode = \"{}\"\n", self.blend_mode.name());
+        manifest += &format!("opacity = {}\n", self.opacity);
+        manifest += &format!("flow = {}\n", self.flow);
+
+        manifest += "\n# grid\n";
+        let grid = &self.grid;
+        manifest += &format!("grid.show = {}\ngrid.snap = {}\n", grid.show, grid.snap);
+        manifest += &format!("grid.cell = {}\n", grid.cell);
+        manifest += &format!(
+            "grid.offset_x = {}\ngrid.offset_y = {}\n",
+            grid.offset[0], grid.offset[1]
+        );
+        let [r, g, b, a] = grid.color.to_srgba_unmultiplied();
+        manifest += &format!("grid.color = \"#{r:02x}{g:02x}{b:02x}{a:02x}\"\n");
+
+        manifest += "\n# symmetry\n";
+        let symmetry = &self.effects.symmetry;
+        manifest += &format!(
+            "symmetry.mode = \"{}\"\n",
+            symmetry_mode_name(symmetry.mode)
+        );
+        manifest += &format!("symmetry.ways = {}\n", symmetry.ways);
+        if let Some(Location { x, y }) = symmetry.center {
+            manifest += &format!("symmetry.center_x = {x}\nsymmetry.center_y = {y}\n");
+        }
+
+        let mut zip = ZipWriter::new();
+        zip.add("manifest.txt", manifest.as_bytes());
+        for (name, data) in files {
+            zip.add(&name, &data);
+        }
+        fs::write(path, zip.finish()).map_err(|e| e.to_string())
+    }
+
+    pub fn load_project(&mut self, path: &Path) -> Result<(), String> {
+        let bytes = fs::read(path).map_err(|e| e.to_string())?;
+        let files: HashMap<String, Vec<u8>> = read_zip(&bytes)?.into_iter().collect();
+        let file = |name: &str| {
+            files
+                .get(name)
+                .ok_or_else(|| format!("{name} is missing from the project"))
+        };
+        let manifest = Manifest::parse(&String::from_utf8_lossy(file("manifest.txt")?));
+        if manifest.str("format") != Some(FORMAT) {
+            return Err("not a project file".to_owned());
+        }
+        let version: u32 = manifest.get("version").unwrap_or(0);
+        if version > VERSION {
+            println!("This project was saved by a newer version, some of it may be missing.");
+        }
+
+        // layers are stacked bottom to top, and flattened since the canvas is a single image
+        let mut image: Option<Array<Color32, 2>> = None;
+        for name in manifest.str("layers").unwrap_or_default().split(',') {
+            let layer =
+                image::load_from_memory(file(name.trim())?).map_err(|e| format!("{name}: {e}"))?;
+            let layer = from_rgba_image(&layer.to_rgba8());
+            image = Some(match image {
+                None => layer,
+                Some(mut below) => {
+                    // a layer that doesn't fit the canvas is left out rather than stretched
+                    if below.size() == layer.size() {
+                        for (px, &top) in below
+                            .as_flattened_mut()
+                            .iter_mut()
+                            .zip(layer.as_flattened())
+                        {
+                            *px = BlendMode::Normal.blend(*px, top, 1.0);
+                        }
+                    }
+                    below
+                }
+            });
+        }
+        let image = image.ok_or("the project has no layers")?;
+        let [w, h] = image.size();
+
+        let mut frames = Vec::new();
+        if let Some(names) = manifest.str("frames") {
+            let durations: Vec<u32> = manifest
+                .str("frame_durations")
+                .unwrap_or_default()
+                .split(',')
+                .map(|d| d.trim().parse().unwrap_or(100))
+                .collect();
+            for (i, name) in names.split(',').enumerate() {
+                let frame = image::load_from_memory(file(name.trim())?)
+                    .map_err(|e| format!("{name}: {e}"))?;
+                frames.push(AnimationFrame {
+                    image: from_rgba_image(&frame.to_rgba8()),
+                    duration: durations.get(i).copied().unwrap_or(100),
+                });
+            }
+        }
+
+        let mut selection = None;
+        if let Some(name) = manifest.str("selection") {
+            let mask = image::load_from_memory(file(name)?)
+                .map_err(|e| format!("{name}: {e}"))?
+                .to_luma8();
+            if mask.dimensions() == (w as u32, h as u32) {
+                selection = Some(Selection::from_mask(
+                    Array::from_flat(mask.into_raw(), [w, h]).unwrap(),
+                ));
+            }
+        }
+        let mut palette = None;
+        if let Some(name) = manifest.str("palette") {
+            let mut loaded = Palette::from_gpl(&String::from_utf8_lossy(file(name)?))?;
+            if loaded.name.is_empty() {
+                loaded.name = "Palette".to_owned();
+            }
+            palette = Some(loaded);
+        }
+
+        // the app only changes once everything is decoded, so a broken file leaves it as it was
+        self.replace_image(image);
+        if frames.is_empty() {
+            self.reset_animation();
+        } else {
+            self.animation
+                .replace(frames, manifest.get("current_frame").unwrap_or(0));
+        }
+        if let Some(selection) = selection {
+            self.selection = selection;
+        }
+        if let Some(palette) = palette {
+            self.palette = palette;
+        }
+
+        if let Some(mode) = Mode::ALL
+            .into_iter()
+            .find(|m| manifest.str("mode") == Some(m.name()))
+        {
+            self.mode = mode;
+        }
+        manifest.read("size", &mut self.draw.size);
+        self.draw.px = manifest.color("primary").unwrap_or(self.draw.px);
+        self.secondary_px = manifest.color("secondary").unwrap_or(self.secondary_px);
+        if let Some(blend_mode) = BlendMode::ALL
+            .into_iter()
+            .find(|m| manifest.str("blend_mode") == Some(m.name()))
+        {
+            self.blend_mode = blend_mode;
+        }
+        manifest.read("opacity", &mut self.opacity);
+        manifest.read("flow", &mut self.flow);
+
+        let grid = &mut self.grid;
+        manifest.read("grid.show", &mut grid.show);
+        manifest.read("grid.snap", &mut grid.snap);
+        manifest.read("grid.cell", &mut grid.cell);
+        manifest.read("grid.offset_x", &mut grid.offset[0]);
+        manifest.read("grid.offset_y", &mut grid.offset[1]);
+        if let Some(rgba) = manifest.color("grid.color") {
+            let [r, g, b, a] = rgba.to_be_bytes();
+            grid.color = Color32::from_rgba_unmultiplied(r, g, b, a);
+        }
+
+        let symmetry = &mut self.effects.symmetry;
+        if let Some(mode) = [
+            SymmetryMode::Off,
+            SymmetryMode::Horizontal,
+            SymmetryMode::Vertical,
+            SymmetryMode::Both,
+            SymmetryMode::Radial,
+        ]
+        .into_iter()
+        .find(|&m| manifest.str("symmetry.mode") == Some(symmetry_mode_name(m)))
+        {
+            symmetry.mode = mode;
+        }
+        manifest.read("symmetry.ways", &mut symmetry.ways);
+        symmetry.center = manifest
+            .get("symmetry.center_x")
+            .zip(manifest.get("symmetry.center_y"))
+            .map(|(x, y)| Location::new(x, y));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_keeps_unknown_and_missing_keys() {
+        let manifest = Manifest::parse(
+            "# saved by a newer version
+            format = \"paint-project\"
+            version = 99
+            layers = layer0.png, layer1.png
+            blend_modes = normal,multiply
+            brush_size = not a number
+            ",
+        );
+        // keys from newer versions are kept, not an error
+        assert_eq!(manifest.str("blend_modes"), Some("normal,multiply"));
+        assert_eq!(manifest.str("format"), Some(FORMAT));
+        assert_eq!(manifest.get::<u32>("version"), Some(99));
+        assert_eq!(manifest.str("layers"), Some("layer0.png, layer1.png"));
+        // missing and invalid values leave what was there
+        let mut size = 5.0f32;
+        manifest.read("brush_size", &mut size);
+        manifest.read("opacity", &mut size);
+        assert_eq!(size, 5.0);
+        assert_eq!(manifest.color("primary_color"), None);
+    }
+
+    #[test]
+    fn manifest_values() {
+        let manifest = Manifest::parse("a=1\nname = \"x = y\"\nprimary_color = #ff8000\nno value");
+        assert_eq!(manifest.get::<i32>("a"), Some(1));
+        assert_eq!(manifest.str("name"), Some("x = y"));
+        assert_eq!(manifest.color("primary_color"), Some(0xff8000));
+        assert_eq!(manifest.str("no value"), None);
+    }
+}
@@ -1,6 +1,6 @@
 use egui::{InputState, Pos2, Rect};
 
-use crate::App;
+use crate::{mode::Mode, App};
 
 impl App {
     pub fn sync(&mut self) {
@@ -13,6 +13,7 @@ impl App {
             // clone the image to reset it, then draw the current state of the pulled brush
             // is this inefficient? yes.
             self.image = self.real_image.clone();
+            self.end_stroke();
             let img_size = self.image.size();
             self.changes.all(Rect::from_min_max(
                 Pos2::ZERO,
@@ -40,15 +41,32 @@ impl App {
             };
 
             // draw:
-            self.mode
-                .into_fn_sized(pull_size[0] as f32, pull_size[1] as f32)(
-                self,
-                self.draw.at(
-                    // this is going to be the center
-                    (pull_start[0] as isize + pull_size[0]) as usize,
-                    (pull_start[1] as isize + pull_size[1]) as usize,
-                ),
-            );
+            if self.mode == Mode::Line {
+                // lines go exactly from start to end, the center + radius would lose a pixel when rounding
+                let end = if inp.modifiers.shift {
+                    [
+                        (pull_start[0] as isize + pull_size[0] * 2) as usize,
+                        (pull_start[1] as isize + pull_size[1] * 2) as usize,
+                    ]
+                } else {
+                    pointer_pos
+                };
+                self.draw_line(
+                    self.draw.at(pull_start[0], pull_start[1]),
+                    self.draw.at(end[0], end[1]),
+                    App::draw_dot,
+                );
+            } else {
+                self.mode
+                    .into_fn_sized(pull_size[0] as f32, pull_size[1] as f32)(
+                    self,
+                    self.draw.at(
+                        // this is going to be the center
+                        (pull_start[0] as isize + pull_size[0]) as usize,
+                        (pull_start[1] as isize + pull_size[1]) as usize,
+                    ),
+                );
+            }
 
             // reset and save the pull if user has stopped pulling
             if !inp.pointer.secondary_down() {
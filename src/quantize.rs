@@ -0,0 +1,409 @@
+//! Reducing the image to a small palette of colors, for indexed images
+
+use std::collections::HashMap;
+
+use egui::*;
+use micro_ndarray::Array;
+
+use crate::{
+    popup::{Popup, PreviewDialog, Previewed},
+    select::Selection,
+    App,
+};
+
+pub type Rgb = [u8; 3];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Quantizer {
+    MedianCut,
+    Octree,
+    KMeans,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+    None,
+    FloydSteinberg,
+    Ordered,
+}
+
+/// Every color of the image and how often it is used
+pub fn color_counts(image: &Array<Color32, 2>) -> Vec<(Rgb, u32)> {
+    let mut counts = HashMap::new();
+    for px in image.as_flattened() {
+        let [r, g, b, _] = px.to_srgba_unmultiplied();
+        *counts.entry([r, g, b]).or_insert(0u32) += 1;
+    }
+    counts.into_iter().collect()
+}
+
+/// Like [`color_counts`], but only the (mostly) selected pixels
+pub fn selected_color_counts(image: &Array<Color32, 2>, selection: &Selection) -> Vec<(Rgb, u32)> {
+    let mut counts = HashMap::new();
+    for ([x, y], px) in image.iter() {
+        if selection.contains(x, y) {
+            let [r, g, b, _] = px.to_srgba_unmultiplied();
+            *counts.entry([r, g, b]).or_insert(0u32) += 1;
+        }
+    }
+    counts.into_iter().collect()
+}
+
+/// Finds (at most) `n` colors that represent the given colors well
+pub fn palette(colors: &[(Rgb, u32)], n: usize, quantizer: Quantizer) -> Vec<Rgb> {
+    if colors.len() <= n {
+        return colors.iter().map(|&(c, _)| c).collect();
+    }
+    match quantizer {
+        Quantizer::MedianCut => median_cut(colors, n),
+        Quantizer::Octree => octree(colors, n),
+        Quantizer::KMeans => k_means(colors, n),
+    }
+}
+
+fn average(colors: &[(Rgb, u32)]) -> Rgb {
+    let mut sum = [0u64; 3];
+    let mut total = 0u64;
+    for &(c, count) in colors {
+        for i in 0..3 {
+            sum[i] += c[i] as u64 * count as u64;
+        }
+        total += count as u64;
+    }
+    sum.map(|s| (s / total.max(1)) as u8)
+}
+
+/// Repeatedly splits the box of colors with the biggest range in half (by pixel count)
+fn median_cut(colors: &[(Rgb, u32)], n: usize) -> Vec<Rgb> {
+    let mut boxes = vec![colors.to_vec()];
+    while boxes.len() < n {
+        // the channel with the biggest range in each box
+        let widest = |b: &Vec<(Rgb, u32)>| {
+            (0..3)
+                .map(|i| {
+                    let min = b.iter().map(|(c, _)| c[i]).min().unwrap_or(0);
+                    let max = b.iter().map(|(c, _)| c[i]).max().unwrap_or(0);
+                    (max - min, i)
+                })
+                .max()
+                .unwrap()
+        };
+        let Some((i, (_, channel))) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| (i, widest(b)))
+            .max_by_key(|&(_, range)| range)
+        else {
+            break; // every box is a single color
+        };
+        let mut b = boxes.swap_remove(i);
+        b.sort_unstable_by_key(|(c, _)| c[channel]);
+        let total: u64 = b.iter().map(|&(_, count)| count as u64).sum();
+        let mut acc = 0;
+        let median = b
+            .iter()
+            .position(|&(_, count)| {
+                acc += count as u64;
+                acc * 2 >= total
+            })
+            .unwrap_or(0);
+        let other = b.split_off((median + 1).clamp(1, b.len() - 1));
+        boxes.push(b);
+        boxes.push(other);
+    }
+    boxes.iter().map(|b| average(b)).collect()
+}
+
+/// Pixel count and the sum of each channel (for the average) of an octree node
+type Node = (u64, [u64; 3]);
+
+/// Groups colors into the nodes of an octree (each level halves the range of every channel),
+/// going as deep as possible while staying at `n` nodes or less
+fn octree(colors: &[(Rgb, u32)], n: usize) -> Vec<Rgb> {
+    // the node a color is in at some depth is the top `depth` bits of each channel
+    let key = |c: Rgb, depth: usize| c.map(|v| (v as u32) >> (8 - depth));
+    let mut levels: Vec<HashMap<[u32; 3], Node>> = vec![HashMap::new(); 9];
+    for &(c, count) in colors {
+        for (depth, level) in levels.iter_mut().enumerate() {
+            let node = level.entry(key(c, depth)).or_insert((0, [0; 3]));
+            node.0 += count as u64;
+            for (sum, v) in node.1.iter_mut().zip(c) {
+                *sum += v as u64 * count as u64;
+            }
+        }
+    }
+    let depth = (0..=8).rev().find(|&d| levels[d].len() <= n).unwrap_or(0);
+    let mut leaves: Vec<(usize, [u32; 3])> = levels[depth].keys().map(|&k| (depth, k)).collect();
+    // split the most used leaves into their children while that still fits
+    leaves.sort_unstable_by_key(|&(d, k)| std::cmp::Reverse(levels[d][&k].0));
+    let mut i = 0;
+    while i < leaves.len() {
+        let (d, k) = leaves[i];
+        if d < 8 {
+            // every node has up to 8 children, one for each combination of the next bits
+            let children: Vec<_> = (0..8)
+                .map(|bits| [0, 1, 2].map(|c| k[c] << 1 | (bits >> c) & 1))
+                .filter(|child| levels[d + 1].contains_key(child))
+                .map(|child| (d + 1, child))
+                .collect();
+            if leaves.len() - 1 + children.len() <= n {
+                leaves.splice(i..=i, children);
+                continue; // the children might be splittable too
+            }
+        }
+        i += 1;
+    }
+    leaves
+        .iter()
+        .map(|&(d, k)| {
+            let (count, sum) = levels[d][&k];
+            sum.map(|s| (s / count.max(1)) as u8)
+        })
+        .collect()
+}
+
+fn distance(a: Rgb, b: Rgb) -> u32 {
+    let d = |i: usize| (a[i] as i32 - b[i] as i32).pow(2) as u32;
+    d(0) + d(1) + d(2)
+}
+
+/// The index of the palette color closest to `c`
+pub fn nearest(palette: &[Rgb], c: Rgb) -> usize {
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|&(_, &p)| distance(p, c))
+        .map_or(0, |(i, _)| i)
+}
+
+/// Starts at the median cut palette and moves each color to the average of the colors closest to it
+fn k_means(colors: &[(Rgb, u32)], n: usize) -> Vec<Rgb> {
+    // too many colors make this very slow, a sample is good enough.
+    // sorted first, so the sample doesn't depend on the order the colors were counted in
+    let mut colors = colors.to_vec();
+    colors.sort_unstable();
+    let step = (colors.len() / 20000).max(1);
+    let sample: Vec<_> = colors.iter().step_by(step).copied().collect();
+    let mut centers = median_cut(&sample, n);
+    for _ in 0..10 {
+        let mut clusters = vec![Vec::new(); centers.len()];
+        for &color in &sample {
+            clusters[nearest(&centers, color.0)].push(color);
+        }
+        let new: Vec<Rgb> = clusters
+            .iter()
+            .zip(&centers)
+            .map(|(cluster, &center)| {
+                if cluster.is_empty() {
+                    center
+                } else {
+                    average(cluster)
+                }
+            })
+            .collect();
+        if new == centers {
+            break;
+        }
+        centers = new;
+    }
+    centers
+}
+
+/// A value between 0 and 1 from a Bayer matrix of size 2^order, for ordered dithering
+pub fn bayer(order: u32, x: usize, y: usize) -> f32 {
+    let xy = x ^ y;
+    let mut v = 0;
+    // interleaves the bits of x ^ y and y in reverse
+    for bit in 0..order {
+        v = (v << 2) | ((xy >> bit) & 1) << 1 | ((y >> bit) & 1);
+    }
+    v as f32 / (1 << (2 * order)) as f32
+}
+
+/// Maps every pixel to a palette index, keeping the alpha as is
+pub fn remap(
+    image: &Array<Color32, 2>,
+    palette: &[Rgb],
+    dither: Dither,
+) -> (Vec<u8>, Array<Color32, 2>) {
+    let [w, h] = image.size();
+    let px = image.as_flattened();
+    let mut indices = vec![0u8; px.len()];
+    // accumulated error for floyd-steinberg
+    let mut error = vec![[0.0f32; 3]; px.len()];
+    // how far ordered dithering may move a color, depends on how far apart the colors are
+    let spread = 255.0 / (palette.len() as f32).cbrt();
+    let mut cache = HashMap::new();
+    for y in 0..h {
+        for x in 0..w {
+            let i = x + y * w;
+            let [r, g, b, _] = px[i].to_srgba_unmultiplied();
+            let c = [r, g, b];
+            let index = match dither {
+                Dither::None => *cache.entry(c).or_insert_with(|| nearest(palette, c)),
+                Dither::Ordered => {
+                    let offset = (bayer(2, x, y) - 0.5) * spread;
+                    nearest(
+                        palette,
+                        c.map(|v| (v as f32 + offset).clamp(0.0, 255.0) as u8),
+                    )
+                }
+                Dither::FloydSteinberg => {
+                    let want = [0, 1, 2].map(|j| c[j] as f32 + error[i][j]);
+                    let index = nearest(palette, want.map(|v| v.clamp(0.0, 255.0) as u8));
+                    let got = palette[index];
+                    // push the difference to the neighbors that aren't done yet
+                    for (dx, dy, weight) in [(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)] {
+                        let (nx, ny) = (x as isize + dx, y + dy);
+                        if nx >= 0 && (nx as usize) < w && ny < h {
+                            for j in 0..3 {
+                                error[nx as usize + ny * w][j] +=
+                                    (want[j] - got[j] as f32) * weight / 16.0;
+                            }
+                        }
+                    }
+                    index
+                }
+            };
+            indices[i] = index as u8;
+        }
+    }
+    let result = indices
+        .iter()
+        .zip(px)
+        .map(|(&i, old)| {
+            let [r, g, b] = palette[i as usize];
+            Color32::from_rgba_unmultiplied(r, g, b, old.a())
+        })
+        .collect();
+    (indices, Array::from_flat(result, [w, h]).unwrap())
+}
+
+pub type ReduceColorsDialog = PreviewDialog<ReduceColors>;
+
+pub struct ReduceColors {
+    /// the colors of the (selected part of the) original image
+    counts: Vec<(Rgb, u32)>,
+    quantizer: Quantizer,
+    colors: usize,
+    dither: Dither,
+    /// the palette of the last result
+    palette: Vec<Rgb>,
+}
+
+impl ReduceColors {
+    pub fn new(app: &App) -> Self {
+        let counts = match app.selection {
+            Some(ref selection) => selected_color_counts(&app.image, selection),
+            None => color_counts(&app.image),
+        };
+        Self {
+            counts,
+            quantizer: Quantizer::MedianCut,
+            colors: 16,
+            dither: Dither::None,
+            palette: Vec::new(),
+        }
+    }
+}
+
+impl Previewed for ReduceColors {
+    fn title(&self) -> &'static str {
+        "Reduce Colors"
+    }
+
+    fn params(&mut self, ui: &mut Ui) -> bool {
+        let mut changed = ui
+            .add(Slider::new(&mut self.colors, 2..=256).text("colors"))
+            .changed();
+        ui.horizontal(|ui| {
+            changed |= ui
+                .radio_value(&mut self.quantizer, Quantizer::MedianCut, "Median cut")
+                .changed();
+            changed |= ui
+                .radio_value(&mut self.quantizer, Quantizer::Octree, "Octree")
+                .changed();
+            changed |= ui
+                .radio_value(&mut self.quantizer, Quantizer::KMeans, "k-means")
+                .changed();
+        });
+        ui.horizontal(|ui| {
+            ui.label("Dithering");
+            changed |= ui
+                .radio_value(&mut self.dither, Dither::None, "None")
+                .changed();
+            changed |= ui
+                .radio_value(&mut self.dither, Dither::FloydSteinberg, "Floyd-Steinberg")
+                .changed();
+            changed |= ui
+                .radio_value(&mut self.dither, Dither::Ordered, "Ordered")
+                .changed();
+        });
+        changed
+    }
+
+    fn result(&mut self, original: &Array<Color32, 2>) -> Array<Color32, 2> {
+        self.palette = palette(&self.counts, self.colors, self.quantizer);
+        let (_, result) = remap(original, &self.palette, self.dither);
+        result
+    }
+
+    fn applied(&mut self, app: &mut App) {
+        // remembered for saving as an indexed image
+        app.indexed_palette = Some(self.palette.clone());
+    }
+}
+
+impl App {
+    pub fn quantize_menu(&mut self, ui: &mut Ui) {
+        if ui.button("Reduce Colors...").clicked() {
+            self.popup = Some(Popup::ReduceColors(ReduceColorsDialog::new(
+                self,
+                ReduceColors::new(self),
+            )));
+            ui.close_menu();
+        }
+    }
+
+    /// The palette to save an indexed image with: the one from Reduce Colors,
+    /// or the image's own colors if there are few enough. Transparent pixels aren't counted.
+    pub fn palette_for_indexed(&mut self, max: usize) -> Vec<Rgb> {
+        let counts: Vec<(Rgb, u32)> = {
+            let mut counts = HashMap::new();
+            for px in self.image.as_flattened() {
+                let [r, g, b, a] = px.to_srgba_unmultiplied();
+                if a >= 128 {
+                    *counts.entry([r, g, b]).or_insert(0u32) += 1;
+                }
+            }
+            counts.into_iter().collect()
+        };
+        if let Some(ref palette) = self.indexed_palette {
+            // the image was changed since if it has other colors, so the palette doesn't fit anymore
+            if counts.iter().all(|(c, _)| palette.contains(c)) {
+                return palette.iter().copied().take(max).collect();
+            }
+            self.indexed_palette = None;
+        }
+        palette(&counts, max, Quantizer::MedianCut)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn k_means_ignores_the_counting_order() {
+        let colors: Vec<(Rgb, u32)> = (0..50_000u32)
+            .map(|i| ([i as u8, (i >> 8) as u8, ((i * 7) >> 4) as u8], i % 5 + 1))
+            .collect();
+        let mut reversed = colors.clone();
+        reversed.reverse();
+        assert_eq!(
+            palette(&colors, 8, Quantizer::KMeans),
+            palette(&reversed, 8, Quantizer::KMeans)
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! Changing the size of the canvas (cropping / extending) and scaling the image
+
+use egui::*;
+use image::imageops::{self, FilterType};
+use micro_ndarray::Array;
+
+use crate::{
+    io::{from_rgba_image, to_rgba_image},
+    popup::Popup,
+    App,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Resample {
+    Nearest,
+    Bilinear,
+    Bicubic,
+    Lanczos,
+}
+
+impl Resample {
+    pub fn filter(self) -> FilterType {
+        match self {
+            Resample::Nearest => FilterType::Nearest,
+            Resample::Bilinear => FilterType::Triangle,
+            Resample::Bicubic => FilterType::CatmullRom,
+            Resample::Lanczos => FilterType::Lanczos3,
+        }
+    }
+
+    pub fn menu(resample: &mut Resample, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.radio_value(resample, Resample::Nearest, "Nearest");
+            ui.radio_value(resample, Resample::Bilinear, "Bilinear");
+            ui.radio_value(resample, Resample::Bicubic, "Bicubic");
+            ui.radio_value(resample, Resample::Lanczos, "Lanczos");
+        });
+    }
+}
+
+pub struct CanvasSizeDialog {
+    size: [usize; 2],
+    /// where the old image stays: 0 = left/top, 1 = center, 2 = right/bottom
+    anchor: [usize; 2],
+    fill: Color32,
+}
+
+impl CanvasSizeDialog {
+    pub fn show(&mut self, app: &mut App, ctx: &Context) -> bool {
+        let mut open = true;
+        let mut done = false;
+        Window::new("Canvas Size")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(DragValue::new(&mut self.size[0]).clamp_range(1..=16384));
+                    ui.label("x");
+                    ui.add(DragValue::new(&mut self.size[1]).clamp_range(1..=16384));
+                    ui.label("px");
+                });
+                ui.label("Anchor");
+                for y in 0..3 {
+                    ui.horizontal(|ui| {
+                        for x in 0..3 {
+                            let text = if self.anchor == [x, y] { "■" } else { "□" };
+                            if ui.button(text).clicked() {
+                                self.anchor = [x, y];
+                            }
+                        }
+                    });
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Fill new area with");
+                    ui.color_edit_button_srgba(&mut self.fill);
+                });
+                if ui.button("Apply").clicked() {
+                    app.resize_canvas(self.size, self.anchor, self.fill);
+                    done = true;
+                }
+            });
+        open && !done
+    }
+}
+
+pub struct ScaleDialog {
+    original: [usize; 2],
+    size: [usize; 2],
+    keep_aspect: bool,
+    resample: Resample,
+}
+
+impl ScaleDialog {
+    pub fn show(&mut self, app: &mut App, ctx: &Context) -> bool {
+        let mut open = true;
+        let mut done = false;
+        Window::new("Scale Image")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let aspect = self.original[1] as f32 / self.original[0] as f32;
+                let mut percent = [
+                    self.size[0] as f32 / self.original[0] as f32 * 100.0,
+                    self.size[1] as f32 / self.original[1] as f32 * 100.0,
+                ];
+                ui.horizontal(|ui| {
+                    let w = ui.add(DragValue::new(&mut self.size[0]).clamp_range(1..=16384));
+                    ui.label("x");
+                    let h = ui.add(DragValue::new(&mut self.size[1]).clamp_range(1..=16384));
+                    ui.label("px");
+                    if self.keep_aspect && w.changed() {
+                        self.size[1] = ((self.size[0] as f32 * aspect).round() as usize).max(1);
+                    }
+                    if self.keep_aspect && h.changed() {
+                        self.size[0] = ((self.size[1] as f32 / aspect).round() as usize).max(1);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let w = ui.add(DragValue::new(&mut percent[0]).suffix("%"));
+                    ui.label("x");
+                    let h = ui.add(DragValue::new(&mut percent[1]).suffix("%"));
+                    let scaled = |axis: usize, p: f32| {
+                        ((self.original[axis] as f32 * p / 100.0).round() as usize).max(1)
+                    };
+                    if w.changed() {
+                        self.size[0] = scaled(0, percent[0]);
+                        if self.keep_aspect {
+                            self.size[1] = scaled(1, percent[0]);
+                        }
+                    }
+                    if h.changed() {
+                        self.size[1] = scaled(1, percent[1]);
+                        if self.keep_aspect {
+                            self.size[0] = scaled(0, percent[1]);
+                        }
+                    }
+                });
+                ui.checkbox(&mut self.keep_aspect, "Keep aspect ratio");
+                Resample::menu(&mut self.resample, ui);
+                if ui.button("Apply").clicked() {
+                    app.scale_image(self.size, self.resample);
+                    done = true;
+                }
+            });
+        open && !done
+    }
+}
+
+impl App {
+    pub fn resize_menu(&mut self, ui: &mut Ui) {
+        let size = self.image.size();
+        if ui.button("Canvas Size...").clicked() {
+            self.popup = Some(Popup::CanvasSize(CanvasSizeDialog {
+                size,
+                anchor: [1, 1],
+                fill: Color32::WHITE,
+            }));
+            ui.close_menu();
+        }
+        if ui.button("Scale Image...").clicked() {
+            self.popup = Some(Popup::Scale(ScaleDialog {
+                original: size,
+                size,
+                keep_aspect: true,
+                resample: Resample::Bilinear,
+            }));
+            ui.close_menu();
+        }
+        let mut follow = self.canvas_size.is_none();
+        if ui.checkbox(&mut follow, "Fit canvas to window").changed() {
+            self.canvas_size = if follow { None } else { Some(size) };
+        }
+    }
+
+    /// Replaces the image, fixing the canvas to its size. The selection is dropped as it wouldn't fit anymore.
+    pub fn replace_image(&mut self, image: Array<Color32, 2>) {
+        let size = image.size();
+        self.image = image;
+        self.canvas_size = Some(size);
+        self.selection = None;
+        self.changes.all(Rect::from_min_max(
+            Pos2::ZERO,
+            Pos2::new(size[0] as f32, size[1] as f32),
+        ));
+    }
+
+    /// Changes the canvas size without scaling, keeping the image at the anchor
+    pub fn resize_canvas(&mut self, size: [usize; 2], anchor: [usize; 2], fill: Color32) {
+        let old_size = self.image.size();
+        // how far the old image moves in the new one, per axis
+        let offset = |axis: usize| {
+            (size[axis] as isize - old_size[axis] as isize) * anchor[axis] as isize / 2
+        };
+        let offset = [offset(0), offset(1)];
+        self.transform_frames(|image| {
+            let mut new_image = Array::new_with(size, fill);
+            for ([x, y], pixel) in image.iter() {
+                let pos = [
+                    (x as isize + offset[0]) as usize,
+                    (y as isize + offset[1]) as usize,
+                ];
+                if let Some(px) = new_image.get_mut(pos) {
+                    *px = *pixel;
+                }
+            }
+            new_image
+        });
+    }
+
+    /// Scales the whole image to a new size
+    pub fn scale_image(&mut self, size: [usize; 2], resample: Resample) {
+        self.transform_frames(|image| {
+            let scaled = imageops::resize(
+                &to_rgba_image(image),
+                size[0] as u32,
+                size[1] as u32,
+                resample.filter(),
+            );
+            from_rgba_image(&scaled)
+        });
+    }
+}
@@ -0,0 +1,197 @@
+//! Brushes that change the pixels under them instead of painting a color: smudge, blur, sharpen, dodge and burn
+
+use egui::*;
+use micro_ndarray::Array;
+
+use crate::{
+    draw::{DrawParams, Location},
+    popup::lerp_color,
+    App,
+};
+
+pub struct Retouch {
+    /// how strong the effect is over one brush width of a stroke
+    pub strength: f32,
+    /// how much of the brush radius fades out
+    pub softness: f32,
+    /// the brush size and the colors the smudge brush is carrying along
+    smudge: Option<(isize, Vec<Option<Color32>>)>,
+}
+
+impl Default for Retouch {
+    fn default() -> Self {
+        Self {
+            strength: 0.5,
+            softness: 0.5,
+            smudge: None,
+        }
+    }
+}
+
+impl Retouch {
+    pub fn menu(app: &mut App, ui: &mut Ui) {
+        let retouch = &mut app.retouch;
+        ui.add(Slider::new(&mut retouch.strength, 0.0..=1.0).text("strength"));
+        ui.add(Slider::new(&mut retouch.softness, 0.0..=1.0).text("softness"));
+    }
+
+    /// Called when a stroke starts, so smudging picks up new colors
+    pub fn start(&mut self) {
+        self.smudge = None;
+    }
+}
+
+impl App {
+    /// The pixels under a round brush and how strongly each is affected
+    fn retouch_dab(&self, draw: DrawParams) -> Vec<(Location, f32)> {
+        let size = draw.size.max(1) as isize;
+        let radius = size as f32;
+        let hard = radius * (1.0 - self.retouch.softness);
+        let img_size = self.image.size();
+        // dabs are stamped about every pixel, so the strength is spread over the brush width
+        let strength = self.retouch.strength / radius;
+        let mut dab = Vec::new();
+        for dy in -size + 1..size {
+            for dx in -size + 1..size {
+                let dist = ((dx * dx + dy * dy) as f32).sqrt();
+                let loc = draw.loc.offset(dx, dy);
+                if dist >= radius || loc.x >= img_size[0] || loc.y >= img_size[1] {
+                    continue;
+                }
+                let selected = self.selection_coverage(loc.x, loc.y);
+                if selected == 0.0 || !self.pattern_allows(loc.x, loc.y) {
+                    continue;
+                }
+                let falloff = if dist <= hard {
+                    1.0
+                } else {
+                    1.0 - (dist - hard) / (radius - hard)
+                };
+                dab.push((loc, strength * falloff * selected));
+            }
+        }
+        dab
+    }
+
+    /// Moves every pixel under the brush towards what `effect` returns for it
+    fn retouch(
+        &mut self,
+        draw: DrawParams,
+        effect: impl Fn(&Array<Color32, 2>, Location) -> Color32,
+    ) {
+        let dab = self.retouch_dab(draw);
+        // computed first so the effect only sees the pixels from before this dab
+        let new: Vec<Color32> = dab
+            .iter()
+            .map(|&(loc, _)| effect(&self.image, loc))
+            .collect();
+        for ((Location { x, y }, amount), new) in dab.into_iter().zip(new) {
+            self.image[[x, y]] = lerp_color(self.image[[x, y]], new, amount.min(1.0));
+            self.changes.push(x, y);
+        }
+    }
+
+    pub fn smudge_dot(&mut self, draw: DrawParams) {
+        let dab = self.retouch_dab(draw);
+        let size = draw.size.max(1) as isize;
+        let width = size * 2 - 1;
+        // the carried colors are a square the size of the brush, by offset from the center
+        let index = |loc: Location| {
+            let dx = loc.x as isize - draw.loc.x as isize + size - 1;
+            let dy = loc.y as isize - draw.loc.y as isize + size - 1;
+            (dx + dy * width) as usize
+        };
+        let carried = match self.retouch.smudge {
+            Some((carried_size, ref mut carried)) if carried_size == size => carried,
+            // the first dab of a stroke (or one with a new size) only picks up the colors
+            _ => {
+                let mut carried = vec![None; (width * width) as usize];
+                for &(loc, _) in &dab {
+                    carried[index(loc)] = Some(self.image[[loc.x, loc.y]]);
+                }
+                self.retouch.smudge = Some((size, carried));
+                return;
+            }
+        };
+        // smudging carries colors a long way, so it isn't spread over the brush width like the others
+        let factor = size as f32;
+        for (loc, amount) in dab {
+            let Some(carry) = carried[index(loc)].as_mut() else {
+                continue;
+            };
+            let new = lerp_color(
+                self.image[[loc.x, loc.y]],
+                *carry,
+                (amount * factor).min(1.0),
+            );
+            self.image[[loc.x, loc.y]] = new;
+            self.changes.push(loc.x, loc.y);
+            *carry = new;
+        }
+    }
+
+    pub fn blur_dot(&mut self, draw: DrawParams) {
+        self.retouch(draw, average_3x3);
+    }
+
+    pub fn sharpen_dot(&mut self, draw: DrawParams) {
+        self.retouch(draw, |image, loc| {
+            let (px, avg) = (
+                image[[loc.x, loc.y]].to_array(),
+                average_3x3(image, loc).to_array(),
+            );
+            let c = |i: usize| {
+                (px[i] as f32 * 2.0 - avg[i] as f32)
+                    .round()
+                    .clamp(0.0, px[3] as f32) as u8
+            };
+            Color32::from_rgba_premultiplied(c(0), c(1), c(2), px[3])
+        });
+    }
+
+    pub fn dodge_dot(&mut self, draw: DrawParams) {
+        self.retouch(draw, |image, loc| {
+            scale_brightness(image[[loc.x, loc.y]], 1.5)
+        });
+    }
+
+    pub fn burn_dot(&mut self, draw: DrawParams) {
+        self.retouch(draw, |image, loc| {
+            scale_brightness(image[[loc.x, loc.y]], 0.5)
+        });
+    }
+}
+
+/// The average of the pixel and its 8 neighbors (the ones inside the image)
+fn average_3x3(image: &Array<Color32, 2>, loc: Location) -> Color32 {
+    let mut sum = [0u32; 4];
+    let mut count = 0;
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            let l = loc.offset(dx, dy);
+            if let Some(c) = image.get([l.x, l.y]) {
+                for (s, c) in sum.iter_mut().zip(c.to_array()) {
+                    *s += c as u32;
+                }
+                count += 1;
+            }
+        }
+    }
+    let [r, g, b, a] = sum.map(|s| ((s + count / 2) / count) as u8);
+    Color32::from_rgba_premultiplied(r, g, b, a)
+}
+
+fn scale_brightness(c: Color32, factor: f32) -> Color32 {
+    let [r, g, b, a] = c.to_srgba_unmultiplied();
+    // lightening pushes towards white, so dark colors brighten too
+    let scale = |c: u8| {
+        let c = c as f32 / 255.0;
+        let c = if factor > 1.0 {
+            1.0 - (1.0 - c) / factor
+        } else {
+            c * factor
+        };
+        (c * 255.0).round() as u8
+    };
+    Color32::from_rgba_unmultiplied(scale(r), scale(g), scale(b), a)
+}
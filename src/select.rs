@@ -0,0 +1,256 @@
+//! Selections limit where painting happens and what some operations apply to
+
+use egui::*;
+use micro_ndarray::Array;
+
+use crate::{draw::Location, fill::Filler, App};
+
+#[derive(Clone)]
+pub struct Selection {
+    /// how much each pixel is selected, 0 = not at all, 255 = fully
+    pub mask: Array<u8, 2>,
+    /// the edges between selected and unselected pixels, in pixel coordinates (cached for rendering)
+    outline: Vec<[[usize; 2]; 2]>,
+}
+
+impl Selection {
+    /// Creates a selection from a mask. Returns None if nothing is selected.
+    pub fn from_mask(mask: Array<u8, 2>) -> Option<Self> {
+        if mask.as_flattened().iter().all(|&m| m == 0) {
+            return None;
+        }
+        let mut this = Self {
+            mask,
+            outline: Vec::new(),
+        };
+        this.update_outline();
+        Some(this)
+    }
+
+    /// Selects the pixels from `a` up to (excluding) `b`, in any order
+    pub fn rect(img_size: [usize; 2], a: Location, b: Location) -> Option<Self> {
+        let min = [a.x.min(b.x), a.y.min(b.y)];
+        let max = [a.x.max(b.x), a.y.max(b.y)];
+        let mut mask = Array::new_with(img_size, 0u8);
+        for x in min[0]..max[0].min(img_size[0]) {
+            for y in min[1]..max[1].min(img_size[1]) {
+                mask[[x, y]] = 255;
+            }
+        }
+        Self::from_mask(mask)
+    }
+
+    /// Is this pixel (mostly) selected?
+    #[inline]
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        self.mask.get([x, y]).is_some_and(|&m| m >= 128)
+    }
+
+    /// How much this pixel is selected (0..1), so feathered edges are painted partially
+    #[inline]
+    pub fn coverage(&self, x: usize, y: usize) -> f32 {
+        self.mask.get([x, y]).map_or(0.0, |&m| m as f32 / 255.0)
+    }
+
+    /// The smallest rectangle containing everything selected, as (min, size)
+    pub fn bounds(&self) -> ([usize; 2], [usize; 2]) {
+        let mut min = self.mask.size();
+        let mut max = [0, 0];
+        for ([x, y], &m) in self.mask.iter() {
+            if m != 0 {
+                min = [min[0].min(x), min[1].min(y)];
+                max = [max[0].max(x), max[1].max(y)];
+            }
+        }
+        (min, [max[0] + 1 - min[0], max[1] + 1 - min[1]])
+    }
+
+    /// Must be called whenever the mask changes
+    pub fn update_outline(&mut self) {
+        self.outline.clear();
+        let size = self.mask.size();
+        for y in 0..size[1] {
+            for x in 0..size[0] {
+                if !self.contains(x, y) {
+                    continue;
+                }
+                // wrapping_sub makes the pixels left/above the image count as unselected
+                if !self.contains(x.wrapping_sub(1), y) {
+                    self.outline.push([[x, y], [x, y + 1]]);
+                }
+                if !self.contains(x + 1, y) {
+                    self.outline.push([[x + 1, y], [x + 1, y + 1]]);
+                }
+                if !self.contains(x, y.wrapping_sub(1)) {
+                    self.outline.push([[x, y], [x + 1, y]]);
+                }
+                if !self.contains(x, y + 1) {
+                    self.outline.push([[x, y + 1], [x + 1, y + 1]]);
+                }
+            }
+        }
+    }
+
+    /// Draws the outline of the selection on top of the canvas
+    pub fn render(&self, app: &App, painter: &Painter, canvas: Rect) {
+        let to_pos = |[x, y]: [usize; 2]| canvas.min + vec2(app.to_point(x), app.to_point(y));
+        for &[a, b] in &self.outline {
+            // black and white so it is visible on any color
+            painter.line_segment([to_pos(a), to_pos(b)], Stroke::new(2.0, Color32::BLACK));
+            painter.line_segment([to_pos(a), to_pos(b)], Stroke::new(1.0, Color32::WHITE));
+        }
+    }
+}
+
+/// How a new selection is combined with the existing one
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Combine {
+    Replace,
+    Add,
+    Subtract,
+    Intersect,
+}
+
+impl Combine {
+    /// Shift adds, alt subtracts, both intersect
+    pub fn from_modifiers(modifiers: Modifiers) -> Self {
+        match (modifiers.shift, modifiers.alt) {
+            (false, false) => Combine::Replace,
+            (true, false) => Combine::Add,
+            (false, true) => Combine::Subtract,
+            (true, true) => Combine::Intersect,
+        }
+    }
+}
+
+pub struct MagicWand {
+    /// how far (per channel) a color may be from the clicked one
+    pub tolerance: u8,
+    /// only the connected region around the click, instead of every matching pixel
+    pub contiguous: bool,
+    pub anti_alias: bool,
+}
+
+impl Default for MagicWand {
+    fn default() -> Self {
+        Self {
+            tolerance: 32,
+            contiguous: true,
+            anti_alias: true,
+        }
+    }
+}
+
+impl MagicWand {
+    pub fn menu(app: &mut App, ui: &mut Ui) {
+        let wand = &mut app.magic_wand;
+        ui.add(Slider::new(&mut wand.tolerance, 0..=255).text("tolerance"));
+        ui.checkbox(&mut wand.contiguous, "Contiguous");
+        ui.checkbox(&mut wand.anti_alias, "Anti-aliased edges");
+        ui.label("Shift adds to the selection, Alt subtracts, both intersect");
+    }
+}
+
+/// Whether two colors are within `tolerance` of each other in every channel
+pub fn similar(a: Color32, b: Color32, tolerance: u8) -> bool {
+    a.to_srgba_unmultiplied()
+        .into_iter()
+        .zip(b.to_srgba_unmultiplied())
+        .all(|(a, b)| a.abs_diff(b) <= tolerance)
+}
+
+/// Softens the edges of a mask by averaging each pixel with its neighbors
+pub fn anti_alias(mask: &Array<u8, 2>) -> Array<u8, 2> {
+    let [w, h] = mask.size();
+    Array::new_by_enumeration(mask.size(), |i| {
+        let (x, y) = (i % w, i / w);
+        let mut sum = 0u32;
+        for sy in y.saturating_sub(1)..(y + 2).min(h) {
+            for sx in x.saturating_sub(1)..(x + 2).min(w) {
+                sum += mask[[sx, sy]] as u32;
+            }
+        }
+        // the edges are blurred to the outside, everything selected before stays fully selected
+        mask[[x, y]].max((sum / 9) as u8)
+    })
+}
+
+impl App {
+    /// Combines a mask with the current selection
+    pub fn combine_selection(&mut self, mask: Array<u8, 2>, combine: Combine) {
+        let old = match self.selection.take() {
+            Some(selection) if combine != Combine::Replace => selection.mask,
+            // no selection is the same as everything, except when adding to it
+            _ if combine == Combine::Add => Array::new_with(mask.size(), 0u8),
+            _ => Array::new_with(mask.size(), 255u8),
+        };
+        let mask = match combine {
+            Combine::Replace => mask,
+            Combine::Add => Array::new_by_enumeration(mask.size(), |i| {
+                old.as_flattened()[i].max(mask.as_flattened()[i])
+            }),
+            Combine::Subtract => Array::new_by_enumeration(mask.size(), |i| {
+                old.as_flattened()[i].min(255 - mask.as_flattened()[i])
+            }),
+            Combine::Intersect => Array::new_by_enumeration(mask.size(), |i| {
+                old.as_flattened()[i].min(mask.as_flattened()[i])
+            }),
+        };
+        self.selection = Selection::from_mask(mask);
+    }
+
+    /// Handles the magic wand tool
+    pub fn magic_wand(&mut self, inp: &InputState, pos: Location) {
+        if !inp.pointer.primary_pressed() {
+            return;
+        }
+        let MagicWand {
+            tolerance,
+            contiguous,
+            anti_alias: smooth,
+        } = self.magic_wand;
+        let Some(&col) = self.image.get([pos.x, pos.y]) else {
+            return;
+        };
+        let mut mask = Array::new_with(self.image.size(), 0u8);
+        if contiguous {
+            let region = Filler::new(&self.image, pos, |c| similar(c, col, tolerance)).region();
+            for Location { x, y } in region {
+                mask[[x, y]] = 255;
+            }
+        } else {
+            for ([x, y], &c) in self.image.iter() {
+                if similar(c, col, tolerance) {
+                    mask[[x, y]] = 255;
+                }
+            }
+        }
+        if smooth {
+            mask = anti_alias(&mask);
+        }
+        self.combine_selection(mask, Combine::from_modifiers(inp.modifiers));
+    }
+
+    /// Handles the rectangle select tool
+    pub fn select_input(&mut self, inp: &InputState, pos: Location) {
+        let pos = self.grid.snap(pos);
+        if inp.pointer.any_down() {
+            let start = *self.select_start.get_or_insert(pos);
+            // None if nothing was dragged out, which just deselects
+            self.selection = Selection::rect(self.image.size(), start, pos);
+        } else {
+            self.select_start = None;
+        }
+    }
+
+    /// Whether painting is allowed at this pixel
+    #[inline]
+    pub fn is_selected(&self, x: usize, y: usize) -> bool {
+        self.selection.as_ref().is_none_or(|s| s.contains(x, y))
+    }
+
+    /// How much painting at this pixel takes effect (0..1), everything counts without a selection
+    pub fn selection_coverage(&self, x: usize, y: usize) -> f32 {
+        self.selection.as_ref().map_or(1.0, |s| s.coverage(x, y))
+    }
+}
@@ -0,0 +1,84 @@
+//! Strokes remember the image from before they started, so overlapping dabs don't build up past the opacity
+
+use egui::Color32;
+use micro_ndarray::Array;
+
+use crate::App;
+
+pub struct StrokeBuffer {
+    /// the image when the stroke started
+    base: Array<Color32, 2>,
+    /// how much each pixel has been painted in this stroke (0..1)
+    coverage: Array<f32, 2>,
+}
+
+impl StrokeBuffer {
+    fn new(image: &Array<Color32, 2>) -> Self {
+        Self {
+            base: image.clone(),
+            coverage: Array::new_with(image.size(), 0.0),
+        }
+    }
+
+    /// The image from before the stroke started
+    pub fn base(&self) -> &Array<Color32, 2> {
+        &self.base
+    }
+
+    /// Raises the coverage of a pixel to at least `strength` and returns it,
+    /// for tools that apply their strongest dab instead of adding them up
+    pub fn cover(&mut self, x: usize, y: usize, strength: f32) -> f32 {
+        let coverage = &mut self.coverage[[x, y]];
+        *coverage = coverage.max(strength);
+        *coverage
+    }
+}
+
+impl App {
+    /// The current stroke, started if there is none yet
+    pub fn stroke_buffer(&mut self) -> &mut StrokeBuffer {
+        // the image may have been resized since the stroke started
+        if self
+            .stroke
+            .as_ref()
+            .is_none_or(|stroke| stroke.base.size() != self.image.size())
+        {
+            self.stroke = Some(StrokeBuffer::new(&self.image));
+        }
+        self.stroke.as_mut().unwrap()
+    }
+
+    /// Paints pixels as part of the current stroke, see [`StrokeBuffer`], leaving out the ones
+    /// outside the pattern and fading out with the selection. `strength` (0..1) scales the flow, for soft brush edges.
+    pub fn stroke_pxs(
+        &mut self,
+        pxs: impl IntoIterator<Item = [usize; 2]>,
+        col: Color32,
+        strength: f32,
+    ) {
+        let (flow, opacity, blend_mode) = (self.flow, self.opacity, self.blend_mode);
+        self.stroke_buffer();
+        // looked up once, not for every mirrored copy
+        let pattern = self.effects.patterns.get(&self.mode);
+        let selection = self.selection.as_ref();
+        let stroke = self.stroke.as_mut().unwrap();
+        for [x, y] in pxs {
+            // partially selected pixels are painted partially
+            let selected = selection.map_or(1.0, |selection| selection.coverage(x, y));
+            if selected == 0.0 || !pattern.is_none_or(|pattern| pattern.allows(x, y)) {
+                continue;
+            }
+            let coverage = &mut stroke.coverage[[x, y]];
+            // each dab covers `flow` of what is left
+            *coverage += (1.0 - *coverage) * flow * strength;
+            let amount = opacity * *coverage * selected;
+            self.image[[x, y]] = blend_mode.blend(stroke.base[[x, y]], col, amount);
+            self.changes.push(x, y);
+        }
+    }
+
+    /// Starts a new stroke the next time something is painted
+    pub fn end_stroke(&mut self) {
+        self.stroke = None;
+    }
+}
@@ -0,0 +1,178 @@
+//! Mirrors every painted pixel around a center point, for icons and mandalas.
+
+use std::f32::consts::PI;
+
+use egui::*;
+
+use crate::{draw::Location, App};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SymmetryMode {
+    Off,
+    /// mirrored left <-> right
+    Horizontal,
+    /// mirrored top <-> bottom
+    Vertical,
+    Both,
+    /// `ways` copies rotated around the center
+    Radial,
+}
+
+use SymmetryMode::*;
+
+pub struct Symmetry {
+    pub mode: SymmetryMode,
+    pub ways: usize,
+    /// None means the center of the image
+    pub center: Option<Location>,
+}
+
+impl Default for Symmetry {
+    fn default() -> Self {
+        Self {
+            mode: Off,
+            ways: 6,
+            center: None,
+        }
+    }
+}
+
+impl Symmetry {
+    pub fn menu(app: &mut App, ui: &mut Ui) {
+        let symmetry = &mut app.effects.symmetry;
+        ui.label("Symmetry");
+        ui.radio_value(&mut symmetry.mode, Off, "Off");
+        ui.radio_value(&mut symmetry.mode, Horizontal, "Horizontal");
+        ui.radio_value(&mut symmetry.mode, Vertical, "Vertical");
+        ui.radio_value(&mut symmetry.mode, Both, "Both");
+        ui.radio_value(&mut symmetry.mode, Radial, "Radial");
+        if symmetry.mode == Radial {
+            ui.add(Slider::new(&mut symmetry.ways, 2..=32).text("ways"));
+        }
+        if symmetry.center.is_some() && ui.button("Reset center").clicked() {
+            symmetry.center = None;
+        }
+    }
+
+    /// The center in pixel coordinates, the middle of the image's pixels by default
+    pub fn center(&self, img_size: [usize; 2]) -> [f32; 2] {
+        match self.center {
+            Some(Location { x, y }) => [x as f32, y as f32],
+            None => img_size.map(|size| (size as f32 - 1.0) / 2.0),
+        }
+    }
+
+    /// All the places a pixel at (x, y) ends up in, including itself (always first).
+    /// Copies are rounded to the nearest pixel, mirrored ones land exactly on a pixel.
+    pub fn points(
+        &self,
+        x: usize,
+        y: usize,
+        img_size: [usize; 2],
+    ) -> impl Iterator<Item = Location> {
+        let [cx, cy] = self.center(img_size);
+        // relative to the symmetry center
+        let (dx, dy) = (x as f32 - cx, y as f32 - cy);
+        let (mode, ways) = (self.mode, self.ways.max(1));
+        let copies = match mode {
+            Off => 1,
+            Horizontal | Vertical => 2,
+            Both => 4,
+            Radial => ways,
+        };
+        (0..copies).map(move |i| {
+            let [dx, dy] = match (mode, i) {
+                (_, 0) => return Location::new(x, y),
+                (Horizontal, _) | (Both, 1) => [-dx, dy],
+                (Vertical, _) | (Both, 2) => [dx, -dy],
+                (Both, _) => [-dx, -dy],
+                _ => {
+                    let (sin, cos) = (PI * 2.0 / ways as f32 * i as f32).sin_cos();
+                    [dx * cos - dy * sin, dx * sin + dy * cos]
+                }
+            };
+            // out of bounds (negative) points wrap around and get ignored later
+            Location::new(
+                (cx + dx).round() as isize as usize,
+                (cy + dy).round() as isize as usize,
+            )
+        })
+    }
+
+    /// Draws the symmetry axes on top of the canvas (not into the image)
+    pub fn render_axes(&self, app: &App, painter: &Painter, canvas: Rect) {
+        let [cx, cy] = self.center(app.image.size());
+        // through the middle of the center pixel
+        let center = canvas.min + vec2(cx + 0.5, cy + 0.5) / app.pixels_per_point;
+        let stroke = Stroke::new(1.0, Color32::from_rgba_unmultiplied(0, 160, 255, 160));
+        let len = canvas.width().max(canvas.height()) * 2.0;
+        let axis = |angle: f32| {
+            let dir = Vec2::angled(angle) * len;
+            painter.line_segment([center - dir, center + dir], stroke);
+        };
+        match self.mode {
+            Off => return,
+            Horizontal => axis(PI / 2.0),
+            Vertical => axis(0.0),
+            Both => {
+                axis(0.0);
+                axis(PI / 2.0);
+            }
+            Radial => {
+                // one ray per copy, starting at the top
+                for i in 0..self.ways {
+                    let dir = Vec2::angled(PI * 2.0 / self.ways as f32 * i as f32 - PI / 2.0);
+                    painter.line_segment([center, center + dir * len], stroke);
+                }
+            }
+        }
+        painter.circle_stroke(center, 4.0, stroke);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(
+        mode: SymmetryMode,
+        ways: usize,
+        x: usize,
+        y: usize,
+        size: [usize; 2],
+    ) -> Vec<[usize; 2]> {
+        let symmetry = Symmetry {
+            mode,
+            ways,
+            center: None,
+        };
+        symmetry
+            .points(x, y, size)
+            .map(|loc| [loc.x, loc.y])
+            .collect()
+    }
+
+    #[test]
+    fn mirrors_onto_the_opposite_pixel() {
+        // even and odd sizes
+        for size in [[8, 6], [7, 5]] {
+            let [w, h] = size;
+            assert_eq!(points(Horizontal, 0, 1, 2, size), [[1, 2], [w - 2, 2]]);
+            assert_eq!(points(Vertical, 0, 1, 2, size), [[1, 2], [1, h - 3]]);
+            assert_eq!(
+                points(Both, 0, 0, 0, size),
+                [[0, 0], [w - 1, 0], [0, h - 1], [w - 1, h - 1]]
+            );
+        }
+    }
+
+    #[test]
+    fn radial_rounds_to_the_nearest_pixel() {
+        assert_eq!(
+            points(Radial, 4, 5, 1, [7, 7]),
+            [[5, 1], [5, 5], [1, 5], [1, 1]]
+        );
+        assert_eq!(points(Radial, 3, 3, 0, [7, 7]).len(), 3);
+        assert_eq!(points(Off, 3, 3, 0, [7, 7]), [[3, 0]]);
+    }
+}
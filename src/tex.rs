@@ -7,26 +7,32 @@ use micro_ndarray::Array;
 use crate::{compress::FlatArea, App};
 
 impl App {
-    /// changes the image size when resizing window
+    /// changes the image size when resizing window, unless the canvas has a fixed size
     pub fn correct_tex_size(&mut self, texman: &mut TextureManager, window_size: [usize; 2]) {
-        if self.image.size() == window_size {
-            return;
-        }
-        // creates a new image and transfers the pixels
-        let mut new_image = Array::new_with([window_size[0], window_size[1]], Color32::WHITE);
-        for (pos, pixel) in self.image.iter() {
-            // if its within the image, set it
-            if let Some(px) = new_image.get_mut(pos) {
-                *px = *pixel;
+        let size = self.canvas_size.unwrap_or(window_size);
+        if self.image.size() != size {
+            // creates a new image and transfers the pixels
+            let mut new_image = Array::new_with([size[0], size[1]], Color32::WHITE);
+            for (pos, pixel) in self.image.iter() {
+                // if its within the image, set it
+                if let Some(px) = new_image.get_mut(pos) {
+                    *px = *pixel;
+                }
             }
-        }
-        self.image = new_image;
+            self.image = new_image;
+            // the selection doesn't match the image anymore
+            self.selection = None;
 
-        // everything changed
-        self.changes.all(Rect::from_min_max(
-            Pos2::ZERO,
-            Pos2::new(window_size[0] as f32, window_size[1] as f32),
-        ));
+            // everything changed
+            self.changes.all(Rect::from_min_max(
+                Pos2::ZERO,
+                Pos2::new(size[0] as f32, size[1] as f32),
+            ));
+        }
+        // the texture might still be the old size if the image was replaced
+        if texman.meta(self.tex).map(|meta| meta.size) == Some(self.image.size()) {
+            return;
+        }
 
         // create a renderable texture from the new image
         let cimg = ColorImage {
@@ -0,0 +1,273 @@
+//! Flipping, rotating and cropping the whole image
+
+use egui::*;
+use micro_ndarray::Array;
+
+use crate::{popup::Popup, App};
+
+/// Flips the image left <-> right
+pub fn flip_horizontal(image: &Array<Color32, 2>) -> Array<Color32, 2> {
+    let [w, h] = image.size();
+    Array::new_by_enumeration([w, h], |i| image[[w - 1 - i % w, i / w]])
+}
+
+/// Flips the image top <-> bottom
+pub fn flip_vertical(image: &Array<Color32, 2>) -> Array<Color32, 2> {
+    let [w, h] = image.size();
+    Array::new_by_enumeration([w, h], |i| image[[i % w, h - 1 - i / w]])
+}
+
+/// Rotates by 90° clockwise `turns` times
+pub fn rotate_90(image: &Array<Color32, 2>, turns: usize) -> Array<Color32, 2> {
+    let [w, h] = image.size();
+    match turns % 4 {
+        0 => image.clone(),
+        1 => Array::new_by_enumeration([h, w], |i| {
+            let (x, y) = (i % h, i / h);
+            image[[y, h - 1 - x]]
+        }),
+        2 => Array::new_by_enumeration([w, h], |i| image[[w - 1 - i % w, h - 1 - i / w]]),
+        _ => Array::new_by_enumeration([h, w], |i| {
+            let (x, y) = (i % h, i / h);
+            image[[w - 1 - y, x]]
+        }),
+    }
+}
+
+/// Rotates by an arbitrary angle (degrees, clockwise) around the center.
+/// If `expand` is set, the canvas grows so that no corners are cut off.
+pub fn rotate(
+    image: &Array<Color32, 2>,
+    degrees: f32,
+    smooth: bool,
+    expand: bool,
+    background: Color32,
+) -> Array<Color32, 2> {
+    let [w, h] = image.size();
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let new_size = if expand {
+        [
+            (w as f32 * cos.abs() + h as f32 * sin.abs()).round() as usize,
+            (w as f32 * sin.abs() + h as f32 * cos.abs()).round() as usize,
+        ]
+    } else {
+        [w, h]
+    };
+    // the centers of both images
+    let (cx, cy) = (w as f32 / 2.0, h as f32 / 2.0);
+    let (ncx, ncy) = (new_size[0] as f32 / 2.0, new_size[1] as f32 / 2.0);
+    Array::new_by_enumeration(new_size, |i| {
+        // rotate each new pixel center back to where it came from
+        let dx = (i % new_size[0]) as f32 + 0.5 - ncx;
+        let dy = (i / new_size[0]) as f32 + 0.5 - ncy;
+        let sx = dx * cos + dy * sin + cx;
+        let sy = -dx * sin + dy * cos + cy;
+        if smooth {
+            sample_bilinear(image, sx - 0.5, sy - 0.5, background)
+        } else {
+            let (x, y) = (sx.floor(), sy.floor());
+            if x < 0.0 || y < 0.0 {
+                return background;
+            }
+            image
+                .get([x as usize, y as usize])
+                .copied()
+                .unwrap_or(background)
+        }
+    })
+}
+
+/// Reads a pixel at a fractional position, mixing the 4 closest ones
+pub fn sample_bilinear(image: &Array<Color32, 2>, x: f32, y: f32, background: Color32) -> Color32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let px = |x: f32, y: f32| -> Rgba {
+        if x < 0.0 || y < 0.0 {
+            return background.into();
+        }
+        image
+            .get([x as usize, y as usize])
+            .copied()
+            .unwrap_or(background)
+            .into()
+    };
+    let top = px(x0, y0) * (1.0 - fx) + px(x0 + 1.0, y0) * fx;
+    let bottom = px(x0, y0 + 1.0) * (1.0 - fx) + px(x0 + 1.0, y0 + 1.0) * fx;
+    (top * (1.0 - fy) + bottom * fy).into()
+}
+
+/// Cuts out a part of the image
+pub fn crop(image: &Array<Color32, 2>, min: [usize; 2], size: [usize; 2]) -> Array<Color32, 2> {
+    Array::new_by_enumeration(size, |i| {
+        image[[min[0] + i % size[0], min[1] + i / size[0]]]
+    })
+}
+
+/// Finds the part of the image that isn't surrounded by the color of the top left corner, as (min, size)
+pub fn trim_bounds(image: &Array<Color32, 2>) -> Option<([usize; 2], [usize; 2])> {
+    let border = *image.get([0, 0])?;
+    let mut min = image.size();
+    let mut max = [0, 0];
+    let mut any = false;
+    for ([x, y], &px) in image.iter() {
+        if px != border {
+            any = true;
+            min = [min[0].min(x), min[1].min(y)];
+            max = [max[0].max(x), max[1].max(y)];
+        }
+    }
+    any.then(|| (min, [max[0] + 1 - min[0], max[1] + 1 - min[1]]))
+}
+
+pub struct RotateDialog {
+    degrees: f32,
+    smooth: bool,
+    expand: bool,
+    background: Color32,
+}
+
+impl RotateDialog {
+    pub fn show(&mut self, app: &mut App, ctx: &Context) -> bool {
+        let mut open = true;
+        let mut done = false;
+        Window::new("Rotate")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.add(Slider::new(&mut self.degrees, -180.0..=180.0).suffix("°"));
+                ui.checkbox(&mut self.smooth, "Smooth (bilinear)");
+                ui.checkbox(&mut self.expand, "Expand canvas to fit");
+                ui.horizontal(|ui| {
+                    ui.label("Background");
+                    ui.color_edit_button_srgba(&mut self.background);
+                });
+                if ui.button("Apply").clicked() {
+                    app.transform_frames(|image| {
+                        rotate(
+                            image,
+                            self.degrees,
+                            self.smooth,
+                            self.expand,
+                            self.background,
+                        )
+                    });
+                    done = true;
+                }
+            });
+        open && !done
+    }
+}
+
+impl App {
+    pub fn transform_menu(&mut self, ui: &mut Ui) {
+        if ui.button("Flip horizontally").clicked() {
+            self.transform_frames(flip_horizontal);
+        }
+        if ui.button("Flip vertically").clicked() {
+            self.transform_frames(flip_vertical);
+        }
+        if ui.button("Rotate 90° clockwise").clicked() {
+            self.transform_frames(|image| rotate_90(image, 1));
+        }
+        if ui.button("Rotate 180°").clicked() {
+            self.transform_frames(|image| rotate_90(image, 2));
+        }
+        if ui.button("Rotate 90° counter-clockwise").clicked() {
+            self.transform_frames(|image| rotate_90(image, 3));
+        }
+        if ui.button("Rotate...").clicked() {
+            self.popup = Some(Popup::Rotate(RotateDialog {
+                degrees: 0.0,
+                smooth: true,
+                expand: true,
+                background: Color32::WHITE,
+            }));
+            ui.close_menu();
+        }
+        ui.separator();
+        if let Some((min, size)) = self.selection.as_ref().map(|s| s.bounds()) {
+            if ui.button("Crop to selection").clicked() {
+                self.transform_frames(|image| crop(image, min, size));
+            }
+        }
+        // trimmed to the current frame, the others are cropped the same way
+        if ui.button("Auto-trim").clicked() {
+            if let Some((min, size)) = trim_bounds(&self.image) {
+                self.transform_frames(|image| crop(image, min, size));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Each pixel holds its own coordinates in red and green
+    fn numbered(size: [usize; 2]) -> Array<Color32, 2> {
+        Array::new_by_enumeration(size, |i| {
+            Color32::from_rgb((i % size[0]) as u8, (i / size[0]) as u8, 0)
+        })
+    }
+
+    /// The original coordinates of every pixel, row by row
+    fn coords(image: &Array<Color32, 2>) -> Vec<[u8; 2]> {
+        image
+            .as_flattened()
+            .iter()
+            .map(|c| [c.r(), c.g()])
+            .collect()
+    }
+
+    #[test]
+    fn rotate_90_turns_clockwise() {
+        // 3 wide, 2 high:
+        // 00 10 20
+        // 01 11 21
+        let image = numbered([3, 2]);
+        let once = rotate_90(&image, 1);
+        assert_eq!(once.size(), [2, 3]);
+        assert_eq!(
+            coords(&once),
+            [[0, 1], [0, 0], [1, 1], [1, 0], [2, 1], [2, 0]]
+        );
+        let twice = rotate_90(&image, 2);
+        assert_eq!(twice.size(), [3, 2]);
+        assert_eq!(
+            coords(&twice),
+            [[2, 1], [1, 1], [0, 1], [2, 0], [1, 0], [0, 0]]
+        );
+        let three = rotate_90(&image, 3);
+        assert_eq!(three.size(), [2, 3]);
+        assert_eq!(
+            coords(&three),
+            [[2, 0], [2, 1], [1, 0], [1, 1], [0, 0], [0, 1]]
+        );
+        assert_eq!(coords(&rotate_90(&image, 4)), coords(&image));
+        assert_eq!(coords(&rotate_90(&once, 3)), coords(&image));
+    }
+
+    #[test]
+    fn crop_keeps_the_rectangle() {
+        let cropped = crop(&numbered([5, 4]), [1, 2], [3, 2]);
+        assert_eq!(cropped.size(), [3, 2]);
+        assert_eq!(
+            coords(&cropped),
+            [[1, 2], [2, 2], [3, 2], [1, 3], [2, 3], [3, 3]]
+        );
+    }
+
+    #[test]
+    fn trim_bounds_finds_the_content() {
+        let mut image = Array::new_with([6, 5], Color32::WHITE);
+        image[[2, 1]] = Color32::RED;
+        image[[4, 3]] = Color32::BLUE;
+        assert_eq!(trim_bounds(&image), Some(([2, 1], [3, 3])));
+        // the corner's color is the border, whatever it is
+        image[[0, 0]] = Color32::RED;
+        assert_eq!(trim_bounds(&image), Some(([0, 0], [6, 5])));
+        assert_eq!(trim_bounds(&Array::new_with([3, 3], Color32::BLACK)), None);
+        assert_eq!(trim_bounds(&Array::new_with([0, 0], Color32::BLACK)), None);
+    }
+}
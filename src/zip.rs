@@ -0,0 +1,225 @@
+//! Just enough of the zip format for the project and OpenRaster files.
+//! Entries are written uncompressed (the PNGs inside are compressed already), deflated ones can be read too.
+
+use std::io::Read;
+
+use flate2::read::DeflateDecoder;
+
+const LOCAL_HEADER: u32 = 0x04034b50;
+const CENTRAL_HEADER: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+
+struct Entry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+/// Builds a zip archive in memory
+#[derive(Default)]
+pub struct ZipWriter {
+    data: Vec<u8>,
+    entries: Vec<Entry>,
+}
+
+impl ZipWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file, stored without compression
+    pub fn add(&mut self, name: &str, contents: &[u8]) {
+        let entry = Entry {
+            name: name.to_owned(),
+            crc: crc32fast::hash(contents),
+            size: contents.len() as u32,
+            offset: self.data.len() as u32,
+        };
+        let data = &mut self.data;
+        data.extend(LOCAL_HEADER.to_le_bytes());
+        data.extend(20u16.to_le_bytes()); // version needed to extract
+        data.extend(0u16.to_le_bytes()); // flags
+        data.extend(0u16.to_le_bytes()); // method: stored
+        data.extend([0; 4]); // modification time and date
+        data.extend(entry.crc.to_le_bytes());
+        data.extend(entry.size.to_le_bytes()); // compressed size
+        data.extend(entry.size.to_le_bytes());
+        data.extend((name.len() as u16).to_le_bytes());
+        data.extend(0u16.to_le_bytes()); // extra field length
+        data.extend(name.as_bytes());
+        data.extend(contents);
+        self.entries.push(entry);
+    }
+
+    /// Writes the central directory and returns the whole archive
+    pub fn finish(mut self) -> Vec<u8> {
+        let start = self.data.len() as u32;
+        let data = &mut self.data;
+        for entry in &self.entries {
+            data.extend(CENTRAL_HEADER.to_le_bytes());
+            data.extend(20u16.to_le_bytes()); // version made by
+            data.extend(20u16.to_le_bytes()); // version needed to extract
+            data.extend(0u16.to_le_bytes()); // flags
+            data.extend(0u16.to_le_bytes()); // method: stored
+            data.extend([0; 4]); // modification time and date
+            data.extend(entry.crc.to_le_bytes());
+            data.extend(entry.size.to_le_bytes());
+            data.extend(entry.size.to_le_bytes());
+            data.extend((entry.name.len() as u16).to_le_bytes());
+            data.extend([0; 12]); // extra and comment length, disk, attributes
+            data.extend(entry.offset.to_le_bytes());
+            data.extend(entry.name.as_bytes());
+        }
+        let size = data.len() as u32 - start;
+        let count = self.entries.len() as u16;
+        data.extend(END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        data.extend([0; 4]); // disk numbers
+        data.extend(count.to_le_bytes());
+        data.extend(count.to_le_bytes());
+        data.extend(size.to_le_bytes());
+        data.extend(start.to_le_bytes());
+        data.extend(0u16.to_le_bytes()); // comment length
+        self.data
+    }
+}
+
+/// Reads every file in a zip archive, in the order they are listed
+pub fn read_zip(bytes: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let u16_at = |i: usize| -> Result<usize, String> {
+        bytes
+            .get(i..i + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+            .ok_or_else(|| "unexpected end of file".to_owned())
+    };
+    let u32_at = |i: usize| -> Result<usize, String> {
+        bytes
+            .get(i..i + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .ok_or_else(|| "unexpected end of file".to_owned())
+    };
+    // the end record is at the very end, unless there is a comment after it
+    let end = (0..bytes.len().saturating_sub(21))
+        .rev()
+        .find(|&i| u32_at(i) == Ok(END_OF_CENTRAL_DIRECTORY as usize))
+        .ok_or("not a zip file")?;
+    let count = u16_at(end + 10)?;
+    let mut pos = u32_at(end + 16)?;
+    let mut files = Vec::with_capacity(count);
+    for _ in 0..count {
+        if u32_at(pos)? != CENTRAL_HEADER as usize {
+            return Err("damaged zip directory".to_owned());
+        }
+        let method = u16_at(pos + 10)?;
+        let crc = u32_at(pos + 16)? as u32;
+        let compressed_size = u32_at(pos + 20)?;
+        let size = u32_at(pos + 24)?;
+        let name_len = u16_at(pos + 28)?;
+        let extra_len = u16_at(pos + 30)?;
+        let comment_len = u16_at(pos + 32)?;
+        let offset = u32_at(pos + 42)?;
+        let name = bytes
+            .get(pos + 46..pos + 46 + name_len)
+            .ok_or("unexpected end of file")?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        pos += 46 + name_len + extra_len + comment_len;
+
+        // the local header may have a different extra field than the central one
+        let data_start = offset + 30 + u16_at(offset + 26)? + u16_at(offset + 28)?;
+        let data = bytes
+            .get(data_start..data_start + compressed_size)
+            .ok_or("unexpected end of file")?;
+        let data = match method {
+            0 => data.to_vec(),
+            8 => {
+                // deflate can't shrink data more than 1032 times, so a bigger size is a lie
+                let mut out = Vec::with_capacity(size.min(compressed_size.saturating_mul(1032)));
+                DeflateDecoder::new(data)
+                    .take(size as u64 + 1)
+                    .read_to_end(&mut out)
+                    .map_err(|e| e.to_string())?;
+                out
+            }
+            _ => return Err(format!("{name} uses an unsupported compression method")),
+        };
+        if data.len() != size || crc32fast::hash(&data) != crc {
+            return Err(format!("{name} is damaged"));
+        }
+        files.push((name, data));
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive() -> Vec<u8> {
+        let mut zip = ZipWriter::new();
+        zip.add("mimetype", b"image/openraster");
+        zip.add("data/empty.png", b"");
+        zip.add("data/layer.png", &(0..=255).collect::<Vec<u8>>());
+        zip.finish()
+    }
+
+    #[test]
+    fn round_trip() {
+        let files = read_zip(&archive()).unwrap();
+        let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["mimetype", "data/empty.png", "data/layer.png"]);
+        assert_eq!(files[0].1, b"image/openraster");
+        assert!(files[1].1.is_empty());
+        assert_eq!(files[2].1, (0..=255).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn empty_archive() {
+        assert!(read_zip(&ZipWriter::new().finish()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn truncated() {
+        let zip = archive();
+        for len in 0..zip.len() {
+            assert!(read_zip(&zip[..len]).is_err(), "cut at {len}");
+        }
+    }
+
+    #[test]
+    fn damaged_contents() {
+        let mut zip = archive();
+        // a byte of mimetype's contents, right after its local header and name
+        zip[30 + 8] ^= 1;
+        assert_eq!(read_zip(&zip).err().unwrap(), "mimetype is damaged");
+    }
+
+    #[test]
+    fn deflated() {
+        use flate2::{write::DeflateEncoder, Compression};
+        use std::io::Write;
+
+        let contents = vec![7; 100_000];
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&contents).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let mut zip = ZipWriter::new();
+        zip.add("data/layer.png", &compressed);
+        let mut zip = zip.finish();
+        // turn the stored entry into a deflated one in both headers
+        let central = zip.len() - 22 - 46 - "data/layer.png".len();
+        for header in [0, central] {
+            let (method, size) = if header == 0 { (8, 22) } else { (10, 24) };
+            zip[header + method] = 8;
+            zip[header + size..header + size + 4]
+                .copy_from_slice(&(contents.len() as u32).to_le_bytes());
+        }
+        let crc = crc32fast::hash(&contents).to_le_bytes();
+        zip[14..18].copy_from_slice(&crc);
+        zip[central + 16..central + 20].copy_from_slice(&crc);
+        assert_eq!(read_zip(&zip).unwrap()[0].1, contents);
+
+        // claiming a larger size than the data inflates to is caught too
+        zip[central + 24..central + 28].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read_zip(&zip).is_err());
+    }
+}